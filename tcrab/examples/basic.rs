@@ -1,7 +1,7 @@
 use tcrab::console::{Console, Color, Canvas, Event, ControlFlow};
 use tcrab::console::event::{KeyCode, ButtonState};
 use tcrab::console::canvas::{Cell, CellBuffer};
-use tcrab_console_gl::TextureRegion;
+use tcrab_console_gl::{AlphaSource, Image, TextureRegion};
 
 const TILESET_IMAGE_DATA: &[u8] = include_bytes!("./terminal.png");
 const TILESET_CELL_WIDTH: u32 = 8;
@@ -19,37 +19,36 @@ fn draw<C: Canvas<CustomGlyph>>(canvas: &mut C, player_pos: (i32, i32)) {
         glyph: '.'.into(),
         foreground_color: Color::from_rgba_u8([25, 25, 25, 255]),
         background_color: Color::from_rgba_u8([15, 15, 15, 255]),
+        ..Cell::default()
     });
     canvas.set_cell(player_pos.0 as usize, player_pos.1 as usize, Cell {
         glyph: '@'.into(),
         foreground_color: Color::from_rgba_u8([255, 0, 0, 255]),
         background_color: Color::BLACK,
+        ..Cell::default()
     });
     canvas.set_cell(15, 10, Cell {
         glyph: CustomGlyph::HappyFace.into(),
         foreground_color: Color::from_rgba_u8([0, 255, 0, 255]),
         background_color: Color::from_rgba_u8([53, 35, 156, 255]),
+        ..Cell::default()
     });
 }
 
 fn main() {
     pretty_env_logger::init();
 
-    let tileset_image = image::load_from_memory(TILESET_IMAGE_DATA)
-        .unwrap()
-        .to_rgba();
+    let tileset_image = Image::load_from_memory(TILESET_IMAGE_DATA, AlphaSource::Alpha).unwrap();
     
     let glyph_lib = create_glyph_lib();
 
     let mut canvas = CellBuffer::new(80, 50, Cell::default());
-    let mut console = tcrab_console_gl::Console::new(tcrab_console_gl::Settings {
-        title: "tcrab example".into(),
-        texture_width: tileset_image.width(),
-        texture_height: tileset_image.height(),
-        texture_data: tileset_image.into_vec(),
-        cell_width: TILESET_CELL_WIDTH,
-        cell_height: TILESET_CELL_HEIGHT,
-    }).unwrap();
+    let mut console = tcrab_console_gl::Console::new(tcrab_console_gl::Settings::new(
+        "tcrab example",
+        tileset_image,
+        TILESET_CELL_WIDTH,
+        TILESET_CELL_HEIGHT,
+    )).unwrap();
 
     let mut player_pos = (5, 10);
     let mut is_running = true;
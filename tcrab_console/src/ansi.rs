@@ -0,0 +1,884 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::canvas::{Attributes, Cell, CellBuffer, CustomGlyph, Glyph};
+use crate::palette::Palette;
+use crate::{cp437, Canvas, Color};
+
+/// The character drawn for `Glyph::Custom` glyphs by default, U+FFFD REPLACEMENT CHARACTER.
+pub const DEFAULT_REPLACEMENT_CHAR: char = '\u{fffd}';
+
+/// The 16 colors of SGR codes 30 to 37 and 90 to 97, as shown by the VGA text mode that ANSI art
+/// is drawn for.
+pub const ANSI_COLORS: [Color; 16] = [
+    Color { r: 0, g: 0, b: 0, a: 255 },
+    Color { r: 170, g: 0, b: 0, a: 255 },
+    Color { r: 0, g: 170, b: 0, a: 255 },
+    Color { r: 170, g: 85, b: 0, a: 255 },
+    Color { r: 0, g: 0, b: 170, a: 255 },
+    Color { r: 170, g: 0, b: 170, a: 255 },
+    Color { r: 0, g: 170, b: 170, a: 255 },
+    Color { r: 170, g: 170, b: 170, a: 255 },
+    Color { r: 85, g: 85, b: 85, a: 255 },
+    Color { r: 255, g: 85, b: 85, a: 255 },
+    Color { r: 85, g: 255, b: 85, a: 255 },
+    Color { r: 255, g: 255, b: 85, a: 255 },
+    Color { r: 85, g: 85, b: 255, a: 255 },
+    Color { r: 255, g: 85, b: 255, a: 255 },
+    Color { r: 85, g: 255, b: 255, a: 255 },
+    Color { r: 255, g: 255, b: 255, a: 255 },
+];
+
+/// The width of ANSI art without SAUCE metadata saying otherwise.
+pub const DEFAULT_ANS_WIDTH: usize = 80;
+
+/// The most cells interpreted text can fill. The cursor doesn't move below the last row that fits,
+/// so cursor movements in damaged files can't make the buffer grow without bounds.
+const MAX_CELLS: usize = 1 << 22;
+
+const DEFAULT_FOREGROUND: Color = ANSI_COLORS[7];
+const DEFAULT_BACKGROUND: Color = ANSI_COLORS[0];
+
+/// The colors a terminal supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorMode {
+    /// 24-bit colors.
+    TrueColor,
+    /// The xterm 256 color palette.
+    Palette256,
+    /// The 16 ANSI colors.
+    Ansi16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TerminalCell {
+    /// `None` for continuation cells, which are left to the terminal.
+    c: Option<char>,
+    foreground_color: Color,
+    background_color: Color,
+    attributes: Attributes,
+}
+
+/// Renders canvases as ANSI escape sequences.
+///
+/// Only the cells that changed since the previous frame are written. Characters a terminal
+/// couldn't draw, such as `Glyph::Custom` glyphs and control characters, are drawn as the
+/// replacement character. Continuation cells are skipped, since terminals draw wide characters
+/// over the next cell themselves. Cell attributes become SGR attributes. Color alpha is ignored.
+#[derive(Debug, Clone)]
+pub struct AnsiRenderer {
+    size: (usize, usize),
+    previous: Vec<TerminalCell>,
+    replacement_char: char,
+    color_mode: ColorMode,
+    /// The palette of the color mode, if it has one.
+    palette: Option<Palette>,
+}
+
+impl AnsiRenderer {
+    /// Creates a renderer using 24-bit colors.
+    pub fn new() -> AnsiRenderer {
+        AnsiRenderer {
+            size: (0, 0),
+            previous: Vec::new(),
+            replacement_char: DEFAULT_REPLACEMENT_CHAR,
+            color_mode: ColorMode::TrueColor,
+            palette: None,
+        }
+    }
+
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Sets the colors used from the next frame on. Colors are replaced with the closest colors
+    /// of the terminal's palette.
+    pub fn set_color_mode(&mut self, color_mode: ColorMode) {
+        self.color_mode = color_mode;
+        self.palette = match color_mode {
+            ColorMode::TrueColor => None,
+            ColorMode::Palette256 => Some(Palette::xterm_256()),
+            ColorMode::Ansi16 => Some(Palette::ansi_16()),
+        };
+        self.reset();
+    }
+
+    pub fn replacement_char(&self) -> char {
+        self.replacement_char
+    }
+
+    pub fn set_replacement_char(&mut self, c: char) {
+        self.replacement_char = c;
+    }
+
+    /// Forgets the previous frame, so the next one is drawn in full.
+    pub fn reset(&mut self) {
+        self.size = (0, 0);
+        self.previous.clear();
+    }
+
+    /// Returns the escape sequences that turn the previous frame into `canvas`. The first frame,
+    /// and any frame with a different size, clears the screen and draws every cell.
+    pub fn render<G, C>(&mut self, canvas: &C) -> String
+    where
+        G: CustomGlyph,
+        C: Canvas<G>,
+    {
+        let mut out = String::new();
+        let (width, height) = canvas.size();
+        let redraw = self.size != (width, height);
+        if redraw {
+            self.size = (width, height);
+            self.previous.clear();
+            self.previous.reserve(width * height);
+            // Reset attributes, hide the cursor and clear the screen.
+            out.push_str("\x1b[0m\x1b[?25l\x1b[H\x1b[2J");
+        }
+
+        let mut cursor = None;
+        // The colors and attributes of the terminal, unknown until the first cell is written.
+        let mut style = None;
+        for y in 0..height {
+            for x in 0..width {
+                let cell = self.terminal_cell(canvas.get_cell(x, y));
+                if redraw {
+                    self.previous.push(cell);
+                } else if self.previous[y * width + x] == cell {
+                    continue;
+                } else {
+                    self.previous[y * width + x] = cell;
+                }
+                let c = match cell.c {
+                    Some(c) => c,
+                    None => continue,
+                };
+                if cursor != Some((x, y)) {
+                    write_cursor_position(&mut out, x, y);
+                }
+                let cell_style = (cell.foreground_color, cell.background_color, cell.attributes);
+                if style != Some(cell_style) {
+                    // Attributes are written with a reset, which also resets the colors.
+                    if style.map(|(_, _, attributes)| attributes) != Some(cell.attributes) {
+                        write_attributes(&mut out, cell.attributes);
+                    }
+                    self.write_cell_colors(&mut out, cell.foreground_color, cell.background_color);
+                    style = Some(cell_style);
+                }
+                out.push(c);
+                // Only ASCII is known to be one column wide; the cursor is moved explicitly
+                // after anything else.
+                cursor = if c.is_ascii() { Some((x + 1, y)) } else { None };
+            }
+        }
+        out
+    }
+
+    fn write_cell_colors(
+        &self,
+        out: &mut String,
+        foreground_color: Color,
+        background_color: Color,
+    ) {
+        let palette = match &self.palette {
+            Some(palette) => palette,
+            None => return write_colors(out, foreground_color, background_color),
+        };
+        // Palettes are never empty.
+        let foreground = palette.nearest_index(foreground_color).unwrap();
+        let background = palette.nearest_index(background_color).unwrap();
+        match self.color_mode {
+            ColorMode::Ansi16 => {
+                let code = |index: usize, base: usize, bright_base: usize| {
+                    if index < 8 { base + index } else { bright_base + index - 8 }
+                };
+                write!(out, "\x1b[{};{}m", code(foreground, 30, 90), code(background, 40, 100))
+            }
+            _ => write!(out, "\x1b[38;5;{};48;5;{}m", foreground, background),
+        }.unwrap();
+    }
+
+    fn terminal_cell<G: CustomGlyph>(&self, cell: Cell<G>) -> TerminalCell {
+        let c = match cell.glyph {
+            Glyph::Char(c) if !c.is_control() => Some(c),
+            Glyph::Char(_) | Glyph::Custom(_) => Some(self.replacement_char),
+            Glyph::Continuation => None,
+        };
+        TerminalCell {
+            c,
+            foreground_color: cell.foreground_color,
+            background_color: cell.background_color,
+            attributes: cell.attributes,
+        }
+    }
+}
+
+impl Default for AnsiRenderer {
+    fn default() -> AnsiRenderer {
+        AnsiRenderer::new()
+    }
+}
+
+/// Writes the CUP sequence that moves the cursor to the zero-based cell `(x, y)`.
+pub fn write_cursor_position(out: &mut String, x: usize, y: usize) {
+    write!(out, "\x1b[{};{}H", y + 1, x + 1).unwrap();
+}
+
+/// Writes an SGR sequence setting 24-bit foreground and background colors.
+pub fn write_colors(out: &mut String, foreground_color: Color, background_color: Color) {
+    write!(
+        out,
+        "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+        foreground_color.r,
+        foreground_color.g,
+        foreground_color.b,
+        background_color.r,
+        background_color.g,
+        background_color.b,
+    ).unwrap();
+}
+
+/// Writes an SGR sequence resetting all attributes and colors, then setting `attributes`.
+pub fn write_attributes(out: &mut String, attributes: Attributes) {
+    const CODES: [(Attributes, &str); 6] = [
+        (Attributes::BOLD, ";1"),
+        (Attributes::ITALIC, ";3"),
+        (Attributes::UNDERLINE, ";4"),
+        (Attributes::BLINK, ";5"),
+        (Attributes::REVERSE, ";7"),
+        (Attributes::STRIKETHROUGH, ";9"),
+    ];
+    out.push_str("\x1b[0");
+    for &(attribute, code) in &CODES {
+        if attributes.contains(attribute) {
+            out.push_str(code);
+        }
+    }
+    out.push('m');
+}
+
+/// Returns a color of the 256 color palette used by `38;5;n` and `48;5;n` SGR codes: the 16 ANSI
+/// colors, a 6×6×6 color cube and 24 shades of grey.
+pub fn palette_256_color(index: u8) -> Color {
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        16..=231 => {
+            let i = index as usize - 16;
+            Color {
+                r: CUBE_LEVELS[i / 36],
+                g: CUBE_LEVELS[i / 6 % 6],
+                b: CUBE_LEVELS[i % 6],
+                a: 255,
+            }
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            Color { r: level, g: level, b: level, a: 255 }
+        }
+    }
+}
+
+/// SAUCE metadata appended to ANSI art by most editors.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sauce {
+    pub title: String,
+    pub author: String,
+    pub group: String,
+    /// The creation date as `CCYYMMDD`.
+    pub date: String,
+    /// The width in characters, if the file type has one.
+    pub width: Option<u16>,
+    /// The height in lines, if the file type has one.
+    pub height: Option<u16>,
+    /// Whether the blink attribute selects bright backgrounds rather than blinking. Blinking
+    /// isn't supported, so it always does.
+    pub ice_colors: bool,
+    pub comments: Vec<String>,
+}
+
+/// ANSI art read by `parse_ans`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnsiArt<C: CustomGlyph> {
+    pub buffer: CellBuffer<C>,
+    pub sauce: Option<Sauce>,
+}
+
+/// Interprets an ANSI art `.ans` file, made of CP437 text and escape sequences as understood by
+/// the MS-DOS ANSI.SYS driver.
+///
+/// The art is as wide as its SAUCE record says, 80 columns otherwise, and as tall as needed. See
+/// `parse_ansi_text` for the supported escape sequences. Control characters that aren't
+/// interpreted are drawn as their CP437 glyphs, and the file ends at the first Ctrl-Z.
+pub fn parse_ans<C: CustomGlyph>(data: &[u8]) -> AnsiArt<C> {
+    let (data, sauce) = split_sauce(data);
+    let width = sauce.as_ref()
+        .and_then(|sauce| sauce.width)
+        .map(|width| width as usize)
+        .unwrap_or(DEFAULT_ANS_WIDTH);
+    let mut interpreter = Interpreter::new(width);
+    for &byte in data {
+        match byte {
+            // Ctrl-Z, the MS-DOS end of file.
+            0x1a => break,
+            b'\n' | b'\r' | b'\t' | 0x1b => interpreter.feed(byte as char),
+            _ => interpreter.feed_printable(cp437::to_char(byte)),
+        }
+    }
+    AnsiArt {
+        buffer: interpreter.into_buffer(),
+        sauce,
+    }
+}
+
+pub fn load_ans<C: CustomGlyph, P: AsRef<Path>>(path: P) -> io::Result<AnsiArt<C>> {
+    Ok(parse_ans(&fs::read(path)?))
+}
+
+/// Interprets text containing ANSI escape sequences, e.g. captured terminal output, into a buffer
+/// `width` columns wide and as tall as needed. Lines longer than `width` wrap. The width is
+/// clamped so that at least one row fits in the most cells interpreted text can fill.
+///
+/// Supported sequences are SGR attributes with 16, 256 and 24-bit colors (`38;2;r;g;b` as well
+/// as PabloDraw's `t` sequence), cursor movement, cursor saving and restoring, and erasing the
+/// screen and lines. Bold selects bright foreground colors, blink selects bright background colors
+/// and reverse swaps the colors, like in ANSI art. Italic, underline and strikethrough become cell
+/// attributes. Other sequences and control characters are ignored.
+pub fn parse_ansi_text<C: CustomGlyph>(text: &str, width: usize) -> CellBuffer<C> {
+    let mut interpreter = Interpreter::new(width);
+    for c in text.chars() {
+        interpreter.feed(c);
+    }
+    interpreter.into_buffer()
+}
+
+/// Splits the SAUCE record and its comments from the end of `data`.
+fn split_sauce(data: &[u8]) -> (&[u8], Option<Sauce>) {
+    const RECORD_SIZE: usize = 128;
+    const COMMENT_SIZE: usize = 64;
+
+    if data.len() < RECORD_SIZE || !data[data.len() - RECORD_SIZE..].starts_with(b"SAUCE00") {
+        return (data, None);
+    }
+    let (mut content, record) = data.split_at(data.len() - RECORD_SIZE);
+    let field = |start: usize, len: usize| decode_sauce_string(&record[start..start + len]);
+    let u16_field = |start: usize| u16::from_le_bytes([record[start], record[start + 1]]);
+
+    let data_type = record[94];
+    let file_type = record[95];
+    // Character data of type ASCII, ANSi or ANSiMation have their size in TInfo1 and TInfo2.
+    let has_size = data_type == 1 && file_type <= 2;
+    let size_field = |start: usize| Some(u16_field(start)).filter(|&size| has_size && size > 0);
+
+    let comment_count = record[104] as usize;
+    let comments_size = 5 + comment_count * COMMENT_SIZE;
+    let mut comments = Vec::new();
+    if comment_count > 0 && content.len() >= comments_size {
+        let comment_block = &content[content.len() - comments_size..];
+        if comment_block.starts_with(b"COMNT") {
+            comments = comment_block[5..]
+                .chunks(COMMENT_SIZE)
+                .map(decode_sauce_string)
+                .collect();
+            content = &content[..content.len() - comments_size];
+        }
+    }
+
+    let sauce = Sauce {
+        title: field(7, 35),
+        author: field(42, 20),
+        group: field(62, 20),
+        date: field(82, 8),
+        width: size_field(96),
+        height: size_field(98),
+        ice_colors: record[105] & 1 != 0,
+        comments,
+    };
+    (content, Some(sauce))
+}
+
+/// Decodes a CP437 SAUCE field, which is padded with spaces or zeros.
+fn decode_sauce_string(bytes: &[u8]) -> String {
+    let s: String = bytes.iter().map(|&byte| cp437::to_char(byte)).collect();
+    s.trim_end_matches(' ').to_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseState {
+    Text,
+    Escape,
+    ControlSequence,
+}
+
+/// A virtual ANSI terminal with unlimited height.
+struct Interpreter<C: CustomGlyph> {
+    width: usize,
+    rows: Vec<Vec<Cell<C>>>,
+    x: usize,
+    y: usize,
+    saved_position: (usize, usize),
+    state: ParseState,
+    params: String,
+    foreground_color: Color,
+    background_color: Color,
+    /// The index of the last ANSI foreground color, which bold makes bright.
+    foreground_index: Option<usize>,
+    background_index: Option<usize>,
+    bold: bool,
+    blink: bool,
+    reverse: bool,
+    /// The attributes without an effect on colors: italic, underline and strikethrough.
+    attributes: Attributes,
+}
+
+impl<C: CustomGlyph> Interpreter<C> {
+    fn new(width: usize) -> Interpreter<C> {
+        Interpreter {
+            width: width.clamp(1, MAX_CELLS),
+            rows: Vec::new(),
+            x: 0,
+            y: 0,
+            saved_position: (0, 0),
+            state: ParseState::Text,
+            params: String::new(),
+            foreground_color: DEFAULT_FOREGROUND,
+            background_color: DEFAULT_BACKGROUND,
+            foreground_index: Some(7),
+            background_index: Some(0),
+            bold: false,
+            blink: false,
+            reverse: false,
+            attributes: Attributes::NONE,
+        }
+    }
+
+    fn into_buffer(self) -> CellBuffer<C> {
+        let mut buffer = CellBuffer::new(self.width, self.rows.len(), Self::blank_cell());
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                buffer.set_cell(x, y, cell);
+            }
+        }
+        buffer
+    }
+
+    fn blank_cell() -> Cell<C> {
+        Cell {
+            glyph: Glyph::Char(' '),
+            foreground_color: DEFAULT_FOREGROUND,
+            background_color: DEFAULT_BACKGROUND,
+            ..Cell::default()
+        }
+    }
+
+    fn feed(&mut self, c: char) {
+        match self.state {
+            ParseState::Text => match c {
+                '\x1b' => self.state = ParseState::Escape,
+                // Most ANSI art uses CRLF line endings, but text from Unix programs only uses LF.
+                '\n' => self.new_line(),
+                '\r' => self.x = 0,
+                '\t' => self.x = ((self.x / 8 + 1) * 8).min(self.width - 1),
+                '\x08' => self.x = self.x.saturating_sub(1),
+                c if c.is_control() => {}
+                c => self.feed_printable(c),
+            },
+            ParseState::Escape => {
+                if c == '[' {
+                    self.params.clear();
+                    self.state = ParseState::ControlSequence;
+                } else {
+                    self.state = ParseState::Text;
+                }
+            }
+            ParseState::ControlSequence => match c {
+                '\x30'..='\x3f' => self.params.push(c),
+                // Intermediate bytes aren't used by any supported sequence.
+                '\x20'..='\x2f' => {}
+                '\x40'..='\x7e' => {
+                    self.state = ParseState::Text;
+                    self.control_sequence(c);
+                }
+                _ => self.state = ParseState::Text,
+            },
+        }
+    }
+
+    /// Draws `c` at the cursor, even if it is a control character.
+    fn feed_printable(&mut self, c: char) {
+        if self.state != ParseState::Text {
+            self.feed(c);
+            return;
+        }
+        let (foreground_color, background_color) = if self.reverse {
+            (self.background_color(), self.foreground_color())
+        } else {
+            (self.foreground_color(), self.background_color())
+        };
+        let (x, y) = (self.x, self.y);
+        *self.cell_mut(x, y) = Cell {
+            glyph: Glyph::Char(c),
+            foreground_color,
+            background_color,
+            attributes: self.attributes,
+            ..Self::blank_cell()
+        };
+        self.x += 1;
+        if self.x == self.width {
+            self.new_line();
+        }
+    }
+
+    fn foreground_color(&self) -> Color {
+        match self.foreground_index {
+            Some(index) if self.bold && index < 8 => ANSI_COLORS[index + 8],
+            _ => self.foreground_color,
+        }
+    }
+
+    fn background_color(&self) -> Color {
+        match self.background_index {
+            Some(index) if self.blink && index < 8 => ANSI_COLORS[index + 8],
+            _ => self.background_color,
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.x = 0;
+        self.move_to_row(self.y.saturating_add(1));
+    }
+
+    /// Moves the cursor to row `y`, or to the last row within `MAX_CELLS`.
+    fn move_to_row(&mut self, y: usize) {
+        self.y = y.min((MAX_CELLS / self.width).max(1) - 1);
+    }
+
+    fn cell_mut(&mut self, x: usize, y: usize) -> &mut Cell<C> {
+        if self.rows.len() <= y {
+            let blank_row = vec![Self::blank_cell(); self.width];
+            self.rows.resize(y + 1, blank_row);
+        }
+        &mut self.rows[y][x]
+    }
+
+    fn erase(&mut self, y: usize, columns: std::ops::Range<usize>) {
+        let cell = Cell { background_color: self.background_color(), ..Self::blank_cell() };
+        for x in columns {
+            *self.cell_mut(x, y) = cell;
+        }
+    }
+
+    fn control_sequence(&mut self, final_byte: char) {
+        // Private sequences, e.g. `ESC[?25h` to show the cursor.
+        if self.params.starts_with(|c: char| "<=>?".contains(c)) {
+            return;
+        }
+        let params: Vec<Option<u32>> = self.params
+            .split(';')
+            .map(|param| param.parse().ok())
+            .collect();
+        let param = |i: usize, default: u32| params.get(i).copied().flatten().unwrap_or(default);
+        let count = param(0, 1).max(1) as usize;
+        match final_byte {
+            'A' => self.y = self.y.saturating_sub(count),
+            'B' => self.move_to_row(self.y.saturating_add(count)),
+            'C' => self.x = (self.x + count).min(self.width - 1),
+            'D' => self.x = self.x.saturating_sub(count),
+            'E' => {
+                self.x = 0;
+                self.move_to_row(self.y.saturating_add(count));
+            }
+            'F' => {
+                self.x = 0;
+                self.y = self.y.saturating_sub(count);
+            }
+            'G' => self.x = (count - 1).min(self.width - 1),
+            'H' | 'f' => {
+                self.move_to_row(param(0, 1).max(1) as usize - 1);
+                self.x = (param(1, 1).max(1) as usize - 1).min(self.width - 1);
+            }
+            'J' => match param(0, 0) {
+                0 => {
+                    let y = self.y;
+                    self.erase(y, self.x..self.width);
+                    self.rows.truncate(y + 1);
+                }
+                1 => {
+                    for y in 0..self.y {
+                        self.erase(y, 0..self.width);
+                    }
+                    let y = self.y;
+                    self.erase(y, 0..self.x + 1);
+                }
+                // ANSI.SYS also moves the cursor home when clearing the screen.
+                _ => {
+                    self.rows.clear();
+                    self.x = 0;
+                    self.y = 0;
+                }
+            },
+            'K' => {
+                let columns = match param(0, 0) {
+                    0 => self.x..self.width,
+                    1 => 0..self.x + 1,
+                    _ => 0..self.width,
+                };
+                let y = self.y;
+                self.erase(y, columns);
+            }
+            's' => self.saved_position = (self.x, self.y),
+            'u' => {
+                let (x, y) = self.saved_position;
+                self.x = x;
+                self.y = y;
+            }
+            'm' => self.select_graphic_rendition(&params),
+            // PabloDraw's 24-bit colors: `ESC[0;r;g;bt` for the background and `ESC[1;r;g;bt` for
+            // the foreground.
+            't' if params.len() == 4 => {
+                let color = Color {
+                    r: param(1, 0).min(255) as u8,
+                    g: param(2, 0).min(255) as u8,
+                    b: param(3, 0).min(255) as u8,
+                    a: 255,
+                };
+                match param(0, 0) {
+                    0 => {
+                        self.background_color = color;
+                        self.background_index = None;
+                    }
+                    1 => {
+                        self.foreground_color = color;
+                        self.foreground_index = None;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[Option<u32>]) {
+        let mut params = params.iter().map(|param| param.unwrap_or(0));
+        while let Some(code) = params.next() {
+            match code {
+                0 => {
+                    self.foreground_color = DEFAULT_FOREGROUND;
+                    self.background_color = DEFAULT_BACKGROUND;
+                    self.foreground_index = Some(7);
+                    self.background_index = Some(0);
+                    self.bold = false;
+                    self.blink = false;
+                    self.reverse = false;
+                    self.attributes = Attributes::NONE;
+                }
+                1 => self.bold = true,
+                3 => self.attributes.insert(Attributes::ITALIC),
+                4 => self.attributes.insert(Attributes::UNDERLINE),
+                5 | 6 => self.blink = true,
+                7 => self.reverse = true,
+                9 => self.attributes.insert(Attributes::STRIKETHROUGH),
+                22 => self.bold = false,
+                23 => self.attributes.remove(Attributes::ITALIC),
+                24 => self.attributes.remove(Attributes::UNDERLINE),
+                25 => self.blink = false,
+                27 => self.reverse = false,
+                29 => self.attributes.remove(Attributes::STRIKETHROUGH),
+                30..=37 | 90..=97 => {
+                    let index = if code >= 90 { code - 90 + 8 } else { code - 30 } as usize;
+                    self.foreground_color = ANSI_COLORS[index];
+                    self.foreground_index = Some(index);
+                }
+                39 => {
+                    self.foreground_color = DEFAULT_FOREGROUND;
+                    self.foreground_index = Some(7);
+                }
+                40..=47 | 100..=107 => {
+                    let index = if code >= 100 { code - 100 + 8 } else { code - 40 } as usize;
+                    self.background_color = ANSI_COLORS[index];
+                    self.background_index = Some(index);
+                }
+                49 => {
+                    self.background_color = DEFAULT_BACKGROUND;
+                    self.background_index = Some(0);
+                }
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params
+                            .next()
+                            .filter(|&index| index <= 255)
+                            .map(|index| palette_256_color(index as u8)),
+                        Some(2) => {
+                            let mut channel = || params.next().unwrap_or(0).min(255) as u8;
+                            Some(Color { r: channel(), g: channel(), b: channel(), a: 255 })
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if code == 38 {
+                            self.foreground_color = color;
+                            self.foreground_index = None;
+                        } else {
+                            self.background_color = color;
+                            self.background_index = None;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl CustomGlyph for Tile {}
+
+    fn text(buffer: &CellBuffer<Tile>, y: usize) -> String {
+        (0..buffer.size().0)
+            .map(|x| match buffer.get_cell(x, y).glyph {
+                Glyph::Char(c) => c,
+                _ => '?',
+            })
+            .collect()
+    }
+
+    /// A SAUCE record for ANSi art of `width` columns, preceded by a comment block.
+    fn sauce(width: u16, comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        if !comments.is_empty() {
+            data.extend_from_slice(b"COMNT");
+            for comment in comments {
+                let mut line = comment.as_bytes().to_vec();
+                line.resize(64, b' ');
+                data.extend(line);
+            }
+        }
+        let mut record = b"SAUCE00".to_vec();
+        for &(field, len) in &[("Title", 35), ("Author", 20), ("Group", 20), ("20201231", 8)] {
+            let mut bytes = field.as_bytes().to_vec();
+            bytes.resize(len, b' ');
+            record.extend(bytes);
+        }
+        record.resize(94, 0);
+        record.extend_from_slice(&[1, 1]);
+        record.extend_from_slice(&width.to_le_bytes());
+        record.extend_from_slice(&25u16.to_le_bytes());
+        record.resize(104, 0);
+        record.extend_from_slice(&[comments.len() as u8, 1]);
+        record.resize(128, 0);
+        data.extend(record);
+        data
+    }
+
+    #[test]
+    fn ans_text_and_colors() {
+        let art = parse_ans::<Tile>(b"\x1b[1;31mHi\x1b[0m\r\n\x1b[44m\xdb\x1a\x1b[32mignored");
+        assert_eq!(art.sauce, None);
+        let buffer = art.buffer;
+        assert_eq!(buffer.size(), (DEFAULT_ANS_WIDTH, 2));
+        assert!(text(&buffer, 0).starts_with("Hi "));
+        assert_eq!(buffer.get_cell(0, 0).foreground_color, ANSI_COLORS[9]);
+        assert_eq!(buffer.get_cell(2, 0).foreground_color, DEFAULT_FOREGROUND);
+        let block = buffer.get_cell(0, 1);
+        assert_eq!(block.glyph, Glyph::Char('█'));
+        assert_eq!(block.background_color, ANSI_COLORS[4]);
+        assert_eq!(block.foreground_color, DEFAULT_FOREGROUND);
+    }
+
+    #[test]
+    fn ans_sauce() {
+        let mut data = b"abcdef".to_vec();
+        data.push(0x1a);
+        data.extend(sauce(4, &["first", "second"]));
+        let art = parse_ans::<Tile>(&data);
+        let sauce = art.sauce.unwrap();
+        assert_eq!(sauce.title, "Title");
+        assert_eq!(sauce.author, "Author");
+        assert_eq!(sauce.group, "Group");
+        assert_eq!(sauce.date, "20201231");
+        assert_eq!((sauce.width, sauce.height), (Some(4), Some(25)));
+        assert!(sauce.ice_colors);
+        assert_eq!(sauce.comments, ["first", "second"]);
+        assert_eq!(art.buffer.size(), (4, 2));
+        assert_eq!(text(&art.buffer, 0), "abcd");
+        assert_eq!(text(&art.buffer, 1), "ef  ");
+    }
+
+    #[test]
+    fn ans_malformed_sauce() {
+        // Comments claimed by the record but missing from the file.
+        let mut record = sauce(0, &[]);
+        record[104] = 200;
+        let art = parse_ans::<Tile>(&record);
+        let sauce = art.sauce.unwrap();
+        assert!(sauce.comments.is_empty());
+        assert_eq!(sauce.width, None);
+        assert_eq!(art.buffer.size().0, DEFAULT_ANS_WIDTH);
+
+        // Too short to hold a record.
+        let art = parse_ans::<Tile>(b"SAUCE00");
+        assert_eq!(art.sauce, None);
+        assert_eq!(text(&art.buffer, 0).trim_end(), "SAUCE00");
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let buffer: CellBuffer<Tile> =
+            parse_ansi_text("abc\x1b[2;5Hd\x1b[1;2H\x1b[Ke\x1b[s\x1b[3Bf\x1b[ug\x1b[A", 6);
+        assert_eq!(buffer.size(), (6, 4));
+        assert_eq!(text(&buffer, 0), "aeg   ");
+        assert_eq!(text(&buffer, 1), "    d ");
+        assert_eq!(text(&buffer, 3), "  f   ");
+    }
+
+    #[test]
+    fn huge_cursor_movements_are_bounded() {
+        for sequence in &["\x1b[4000000000B", "\x1b[4000000000;1H", "\x1b[4000000000E"] {
+            let buffer: CellBuffer<Tile> = parse_ansi_text(&format!("{}x", sequence), 80);
+            let (width, height) = buffer.size();
+            assert!(width * height <= MAX_CELLS);
+            assert_eq!(text(&buffer, height - 1).trim_end(), "x");
+        }
+        let buffer: CellBuffer<Tile> = parse_ansi_text(&"\n".repeat(100), 1 << 21);
+        assert_eq!(buffer.size(), (1 << 21, 0));
+        let buffer: CellBuffer<Tile> = parse_ansi_text(&"\n".repeat(100), usize::MAX);
+        assert_eq!(buffer.size(), (MAX_CELLS, 0));
+    }
+
+    #[test]
+    fn huge_widths_are_clamped() {
+        let buffer: CellBuffer<Tile> = parse_ansi_text("x", usize::MAX);
+        assert_eq!(buffer.size(), (MAX_CELLS, 1));
+        assert_eq!(buffer.get_cell(0, 0).glyph, Glyph::Char('x'));
+        assert_eq!(buffer.get_cell(MAX_CELLS - 1, 0).glyph, Glyph::Char(' '));
+    }
+
+    #[test]
+    fn sgr_colors() {
+        let buffer: CellBuffer<Tile> =
+            parse_ansi_text("\x1b[38;5;196;48;2;1;2;3ma\x1b[38;5;256mb\x1b[0;7;33mc", 3);
+        let a = buffer.get_cell(0, 0);
+        assert_eq!(a.foreground_color, Color { r: 255, g: 0, b: 0, a: 255 });
+        assert_eq!(a.background_color, Color { r: 1, g: 2, b: 3, a: 255 });
+        // Out of range palette indices are ignored.
+        assert_eq!(buffer.get_cell(1, 0).foreground_color, a.foreground_color);
+        let c = buffer.get_cell(2, 0);
+        assert_eq!((c.foreground_color, c.background_color), (ANSI_COLORS[0], ANSI_COLORS[3]));
+    }
+
+    #[test]
+    fn palette_256() {
+        assert_eq!(palette_256_color(1), ANSI_COLORS[1]);
+        assert_eq!(palette_256_color(16), Color { r: 0, g: 0, b: 0, a: 255 });
+        assert_eq!(palette_256_color(231), Color { r: 255, g: 255, b: 255, a: 255 });
+        assert_eq!(palette_256_color(232), Color { r: 8, g: 8, b: 8, a: 255 });
+        assert_eq!(palette_256_color(255), Color { r: 238, g: 238, b: 238, a: 255 });
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ansi::AnsiRenderer;
+use crate::canvas::{CustomGlyph, GlyphLibrary};
+use crate::{Canvas, Console, ControlFlow, Event};
+
+/// Writes canvases as an asciicast v2 recording, which can be played back with asciinema.
+///
+/// Each frame is written as an output event holding the ANSI escapes that update the terminal
+/// from the previous frame, see `AnsiRenderer`. The header is written with the first frame, using
+/// its size as the terminal size. Later size changes are written as resize events.
+pub struct AsciicastRecorder<W: Write> {
+    writer: W,
+    renderer: AnsiRenderer,
+    title: Option<String>,
+    size: Option<(usize, usize)>,
+}
+
+impl<W: Write> AsciicastRecorder<W> {
+    pub fn new(writer: W) -> AsciicastRecorder<W> {
+        AsciicastRecorder {
+            writer,
+            renderer: AnsiRenderer::new(),
+            title: None,
+            size: None,
+        }
+    }
+
+    /// Sets the title stored in the header. Has no effect once a frame has been recorded.
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_owned());
+    }
+
+    /// The character written for `Glyph::Custom` glyphs.
+    pub fn set_replacement_char(&mut self, c: char) {
+        self.renderer.set_replacement_char(c);
+    }
+
+    /// Adds a frame shown from `timestamp`, measured from the start of the recording. Frames
+    /// identical to the previous one aren't written.
+    pub fn record_frame<G, C>(&mut self, canvas: &C, timestamp: Duration) -> io::Result<()>
+    where
+        G: CustomGlyph,
+        C: Canvas<G>,
+    {
+        let (width, height) = canvas.size();
+        match self.size {
+            None => self.write_header(width, height)?,
+            Some(size) if size != (width, height) => {
+                let size = format!("{}x{}", width, height);
+                self.write_event(timestamp, "r", &size)?;
+            }
+            Some(_) => {}
+        }
+        self.size = Some((width, height));
+
+        let output = self.renderer.render(canvas);
+        if !output.is_empty() {
+            self.write_event(timestamp, "o", &output)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the recording and gives back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self, width: usize, height: usize) -> io::Result<()> {
+        let mut header = format!(r#"{{"version": 2, "width": {}, "height": {}"#, width, height);
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            write!(header, r#", "timestamp": {}"#, now.as_secs()).unwrap();
+        }
+        if let Some(title) = &self.title {
+            header.push_str(r#", "title": "#);
+            write_json_string(&mut header, title);
+        }
+        header.push('}');
+        writeln!(self.writer, "{}", header)
+    }
+
+    fn write_event(&mut self, timestamp: Duration, code: &str, data: &str) -> io::Result<()> {
+        let mut event = format!("[{:.6}, \"{}\", ", timestamp.as_secs_f64(), code);
+        write_json_string(&mut event, data);
+        event.push(']');
+        writeln!(self.writer, "{}", event)
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Wraps a console to record every presented canvas as an asciicast, timed by when it was
+/// presented.
+pub struct AsciicastRecording<C: Console, W: Write> {
+    console: C,
+    recorder: AsciicastRecorder<W>,
+    start: Instant,
+    error: Option<io::Error>,
+}
+
+impl<C: Console, W: Write> AsciicastRecording<C, W> {
+    pub fn new(console: C, recorder: AsciicastRecorder<W>) -> AsciicastRecording<C, W> {
+        AsciicastRecording {
+            console,
+            recorder,
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    pub fn console(&self) -> &C {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut C {
+        &mut self.console
+    }
+
+    /// Finishes the recording and gives back the console. Returns the first error that occurred
+    /// while recording, if any.
+    pub fn finish(self) -> io::Result<C> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.recorder.finish()?;
+        Ok(self.console)
+    }
+}
+
+impl<C: Console, W: Write> Console for AsciicastRecording<C, W> {
+    type GlyphDef = C::GlyphDef;
+
+    fn wait_for_events_forever<F>(&mut self, event_handler: F)
+    where
+        F: FnMut(Event) -> ControlFlow,
+    {
+        self.console.wait_for_events_forever(event_handler);
+    }
+
+    fn present<G, V>(&mut self, canvas: &V, glyph_lib: &GlyphLibrary<G, C::GlyphDef>)
+    where
+        G: CustomGlyph,
+        V: Canvas<G>,
+    {
+        self.console.present(canvas, glyph_lib);
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.recorder.record_frame(canvas, self.start.elapsed()) {
+            self.error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::{Cell, CellBuffer};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl CustomGlyph for Tile {}
+
+    fn lines(recorder: AsciicastRecorder<Vec<u8>>) -> Vec<String> {
+        let data = recorder.finish().unwrap();
+        String::from_utf8(data).unwrap().lines().map(str::to_owned).collect()
+    }
+
+    fn canvas(width: usize, height: usize) -> CellBuffer<Tile> {
+        CellBuffer::new(width, height, Cell { glyph: '.'.into(), ..Cell::default() })
+    }
+
+    #[test]
+    fn json_strings_escape_quotes_and_control_characters() {
+        let mut out = String::new();
+        write_json_string(&mut out, "a\"b\\c\n\r\t\x1b\x00\u{e9}");
+        assert_eq!(out, r#""a\"b\\c\n\r\t\u001b\u0000é""#);
+    }
+
+    #[test]
+    fn header_uses_the_first_frame_size_and_title() {
+        let mut recorder = AsciicastRecorder::new(Vec::new());
+        recorder.set_title("say \"hi\"");
+        recorder.record_frame(&canvas(3, 2), Duration::ZERO).unwrap();
+        let lines = lines(recorder);
+        assert!(lines[0].starts_with(r#"{"version": 2, "width": 3, "height": 2, "timestamp": "#));
+        assert!(lines[0].ends_with(r#", "title": "say \"hi\""}"#));
+    }
+
+    #[test]
+    fn events_are_timed_from_the_start() {
+        let mut recorder = AsciicastRecorder::new(Vec::new());
+        recorder.record_frame(&canvas(1, 1), Duration::from_millis(1500)).unwrap();
+        let lines = lines(recorder);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with(r#"[1.500000, "o", "\u001b[0m"#));
+        assert!(lines[1].ends_with(r#"."]"#));
+    }
+
+    #[test]
+    fn only_changes_are_written() {
+        let mut recorder = AsciicastRecorder::new(Vec::new());
+        let mut canvas = canvas(3, 2);
+        recorder.record_frame(&canvas, Duration::ZERO).unwrap();
+        recorder.record_frame(&canvas, Duration::from_secs(1)).unwrap();
+        canvas.set_cell(2, 1, Cell { glyph: 'x'.into(), ..Cell::default() });
+        recorder.record_frame(&canvas, Duration::from_secs(2)).unwrap();
+        let lines = lines(recorder);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(r#"\u001b[2J"#));
+        let data = lines[2].strip_prefix(r#"[2.000000, "o", "#).unwrap();
+        assert!(data.starts_with(r#""\u001b[2;3H"#));
+        assert!(data.ends_with(r#"x"]"#));
+        assert!(!data.contains('.'));
+    }
+
+    #[test]
+    fn size_changes_write_a_resize_event_and_redraw() {
+        let mut recorder = AsciicastRecorder::new(Vec::new());
+        recorder.record_frame(&canvas(3, 2), Duration::ZERO).unwrap();
+        recorder.record_frame(&canvas(4, 1), Duration::from_secs(2)).unwrap();
+        let lines = lines(recorder);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains(r#""width": 3, "height": 2"#));
+        assert_eq!(lines[2], r#"[2.000000, "r", "4x1"]"#);
+        let data = lines[3].strip_prefix(r#"[2.000000, "o", "#).unwrap();
+        assert!(data.contains(r#"\u001b[2J"#));
+        assert_eq!(data.matches('.').count(), 4);
+    }
+}
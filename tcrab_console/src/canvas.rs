@@ -1,5 +1,6 @@
 use std::hash::Hash;
 use std::collections::HashMap;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
 
 #[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
@@ -10,9 +11,13 @@ pub trait CustomGlyph: Eq + Hash + Copy {}
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Glyph<C: CustomGlyph> {
     Char(char),
     Custom(C),
+    /// A cell covered by a glyph that spans several cells, set in the cell at its top left. Only
+    /// the background of a continuation cell is drawn.
+    Continuation,
 }
 
 impl<C: CustomGlyph> From<char> for Glyph<C> {
@@ -42,17 +47,114 @@ impl<C: CustomGlyph, D> GlyphLibrary<C, D> {
         self.glyphs.insert(glyph.into(), def);
     }
 
+    pub fn contains_glyph<T: Into<Glyph<C>>>(&self, glyph: T) -> bool {
+        self.glyphs.contains_key(&glyph.into())
+    }
+
     pub fn get_glyph_def(&self, glyph: Glyph<C>) -> &D {
         self.glyphs.get(&glyph).unwrap()
     }
 }
 
+/// Text attributes of a cell, combined with `|`. Renderers draw the ones they can.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+    pub const BOLD: Attributes = Attributes(1 << 0);
+    pub const ITALIC: Attributes = Attributes(1 << 1);
+    pub const UNDERLINE: Attributes = Attributes(1 << 2);
+    /// Swaps the foreground and background colors.
+    pub const REVERSE: Attributes = Attributes(1 << 3);
+    pub const BLINK: Attributes = Attributes(1 << 4);
+    pub const STRIKETHROUGH: Attributes = Attributes(1 << 5);
+    pub const ALL: Attributes = Attributes(0b11_1111);
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Ignores bits that aren't attributes.
+    pub fn from_bits_truncate(bits: u8) -> Attributes {
+        Attributes(bits & Attributes::ALL.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all of `other` is set.
+    pub fn contains(self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Attributes) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Attributes) {
+        self.0 &= !other.0;
+    }
+
+    /// Inserts or removes `other`.
+    pub fn set(&mut self, other: Attributes, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Attributes;
+
+    fn bitor(self, other: Attributes) -> Attributes {
+        Attributes(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Attributes {
+    fn bitor_assign(&mut self, other: Attributes) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for Attributes {
+    type Output = Attributes;
+
+    fn bitand(self, other: Attributes) -> Attributes {
+        Attributes(self.0 & other.0)
+    }
+}
+
+impl BitAndAssign for Attributes {
+    fn bitand_assign(&mut self, other: Attributes) {
+        self.0 &= other.0;
+    }
+}
+
+/// Data passed through untouched to custom cell shaders, e.g. to mark water that shimmers. The
+/// meaning of every bit is up to the game. Renderers without custom shaders ignore it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UserData {
+    pub flags: u8,
+    pub params: [u8; 3],
+}
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Cell<C: CustomGlyph> {
     pub glyph: Glyph<C>,
     pub foreground_color: Color,
     pub background_color: Color,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attributes: Attributes,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub user_data: UserData,
 }
 
 impl<C: CustomGlyph> Default for Cell<C> {
@@ -61,6 +163,8 @@ impl<C: CustomGlyph> Default for Cell<C> {
             glyph: ' '.into(),
             foreground_color: Color::WHITE,
             background_color: Color::BLACK,
+            attributes: Attributes::NONE,
+            user_data: UserData::default(),
         }
     }
 }
@@ -71,6 +175,57 @@ pub trait Canvas<C: CustomGlyph> {
     fn get_cell(&self, x: usize, y: usize) -> Cell<C>;
     fn set_cell(&mut self, x: usize, y: usize, cell: Cell<C>);
 
+    /// Sets a cell whose glyph covers `span_width` by `span_height` cells, such as a double-width
+    /// character. The other covered cells become continuations with the same colors. Covered
+    /// cells outside of the canvas are ignored.
+    ///
+    /// Like in a terminal, a wide glyph that is partly overwritten is erased: its other cells in
+    /// the covered rows become blank. Glyphs spanning several rows should be overwritten whole.
+    fn set_spanning_cell(
+        &mut self,
+        x: usize,
+        y: usize,
+        cell: Cell<C>,
+        span_width: usize,
+        span_height: usize,
+    ) {
+        let (width, height) = self.size();
+        let right = x.saturating_add(span_width).min(width);
+        let bottom = y.saturating_add(span_height).min(height);
+        for cell_y in y..bottom {
+            if x < right {
+                if let Glyph::Continuation = self.get_cell(x, cell_y).glyph {
+                    // The glyph covering the left edge starts further left.
+                    let mut cell_x = x;
+                    while cell_x > 0 {
+                        cell_x -= 1;
+                        let covered = self.get_cell(cell_x, cell_y);
+                        self.set_cell(cell_x, cell_y, Cell { glyph: Glyph::Char(' '), ..covered });
+                        if covered.glyph != Glyph::Continuation {
+                            break;
+                        }
+                    }
+                }
+            }
+            let mut cell_x = right;
+            while cell_x < width {
+                let covered = self.get_cell(cell_x, cell_y);
+                if covered.glyph != Glyph::Continuation {
+                    break;
+                }
+                self.set_cell(cell_x, cell_y, Cell { glyph: Glyph::Char(' '), ..covered });
+                cell_x += 1;
+            }
+            for cell_x in x..right {
+                if (cell_x, cell_y) == (x, y) {
+                    self.set_cell(x, y, cell);
+                } else {
+                    self.set_cell(cell_x, cell_y, Cell { glyph: Glyph::Continuation, ..cell });
+                }
+            }
+        }
+    }
+
     fn fill(&mut self, cell: Cell<C>) {
         let (width, height) = self.size();
         for y in 0..height {
@@ -111,4 +266,66 @@ impl<C: CustomGlyph> Canvas<C> for CellBuffer<C> {
     fn set_cell(&mut self, x: usize, y: usize, cell: Cell<C>) {
         self.cells[y * self.width + x] = cell;
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl CustomGlyph for Tile {}
+
+    fn glyphs(canvas: &CellBuffer<Tile>, y: usize) -> Vec<Glyph<Tile>> {
+        (0..canvas.size().0).map(|x| canvas.get_cell(x, y).glyph).collect()
+    }
+
+    fn wide(c: char) -> Cell<Tile> {
+        Cell { glyph: c.into(), ..Cell::default() }
+    }
+
+    #[test]
+    fn spanning_cell_sets_continuations() {
+        let mut canvas = CellBuffer::new(3, 3, Cell::default());
+        canvas.set_spanning_cell(1, 1, wide('a'), 2, 2);
+        let blank = Glyph::Char(' ');
+        assert_eq!(glyphs(&canvas, 0), [blank; 3]);
+        assert_eq!(glyphs(&canvas, 1), [blank, Glyph::Char('a'), Glyph::Continuation]);
+        assert_eq!(glyphs(&canvas, 2), [blank, Glyph::Continuation, Glyph::Continuation]);
+    }
+
+    #[test]
+    fn spanning_cell_is_clipped_to_the_canvas() {
+        let mut canvas = CellBuffer::new(3, 1, Cell::default());
+        canvas.set_spanning_cell(2, 0, wide('a'), usize::MAX, usize::MAX);
+        assert_eq!(glyphs(&canvas, 0), [Glyph::Char(' '), Glyph::Char(' '), Glyph::Char('a')]);
+    }
+
+    #[test]
+    fn overwriting_the_right_half_erases_the_left_half() {
+        let mut canvas = CellBuffer::new(4, 1, Cell::default());
+        canvas.set_spanning_cell(0, 0, wide('a'), 2, 1);
+        canvas.set_spanning_cell(1, 0, wide('b'), 2, 1);
+        let expected = [Glyph::Char(' '), Glyph::Char('b'), Glyph::Continuation, Glyph::Char(' ')];
+        assert_eq!(glyphs(&canvas, 0), expected);
+    }
+
+    #[test]
+    fn overwriting_the_left_half_erases_the_right_half() {
+        let mut canvas = CellBuffer::new(4, 1, Cell::default());
+        canvas.set_spanning_cell(1, 0, wide('a'), 3, 1);
+        canvas.set_spanning_cell(0, 0, wide('b'), 2, 1);
+        let expected = [Glyph::Char('b'), Glyph::Continuation, Glyph::Char(' '), Glyph::Char(' ')];
+        assert_eq!(glyphs(&canvas, 0), expected);
+    }
+
+    #[test]
+    fn erased_cells_keep_their_colors() {
+        let mut canvas = CellBuffer::new(2, 1, Cell::default());
+        let red = Cell { background_color: Color::RED, ..wide('a') };
+        canvas.set_spanning_cell(0, 0, red, 2, 1);
+        canvas.set_spanning_cell(1, 0, wide('b'), 1, 1);
+        assert_eq!(canvas.get_cell(0, 0), Cell { glyph: Glyph::Char(' '), ..red });
+        assert_eq!(canvas.get_cell(1, 0), wide('b'));
+    }
+}
@@ -1,6 +1,15 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
+use std::str::FromStr;
+
 #[cfg(feature = "serde")]
 use serde::{Serialize, Deserialize};
 
+use crate::css_colors::CSS_COLORS;
+use crate::palette::Palette;
+
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Color {
@@ -34,4 +43,828 @@ impl Color {
             self.a as f32 / 255.0,
         ]
     }
-}
\ No newline at end of file
+
+    /// Returns the red, green and blue components with the sRGB transfer function removed, so
+    /// they are proportional to light intensity.
+    pub fn to_linear_rgb_f32(self) -> [f32; 3] {
+        let [r, g, b] = self.to_rgb_f32();
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)]
+    }
+
+    /// Creates an opaque color from linear red, green and blue components between 0 and 1.
+    pub fn from_linear_rgb_f32(rgb: [f32; 3]) -> Color {
+        let channel = |c: f32| (linear_to_srgb(c) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Color { r: channel(rgb[0]), g: channel(rgb[1]), b: channel(rgb[2]), a: 255 }
+    }
+
+    /// Returns the Oklab lightness, green-red and blue-yellow components. Distances in Oklab
+    /// match perceived color differences better than in RGB.
+    pub fn to_oklab(self) -> [f32; 3] {
+        let [r, g, b] = self.to_linear_rgb_f32();
+        let l = (0.412_221_47 * r + 0.536_332_54 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+        [
+            0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        ]
+    }
+
+    /// Creates an opaque color from Oklab components. Colors outside of sRGB are clipped.
+    pub fn from_oklab(lab: [f32; 3]) -> Color {
+        let [lightness, a, b] = lab;
+        let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+        let m = (lightness - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+        let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+        Color::from_linear_rgb_f32([
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        ])
+    }
+
+    /// Returns the CIELAB `L*`, `a*` and `b*` components, relative to the D65 white point.
+    pub fn to_lab(self) -> [f32; 3] {
+        let [r, g, b] = self.to_linear_rgb_f32();
+        let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+        let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+        let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+        let (fx, fy, fz) = (lab_f(x), lab_f(y), lab_f(z));
+        [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+    }
+
+    /// Creates an opaque color from CIELAB components relative to the D65 white point. Colors
+    /// outside of sRGB are clipped.
+    pub fn from_lab(lab: [f32; 3]) -> Color {
+        let fy = (lab[0] + 16.0) / 116.0;
+        let x = lab_f_inverse(fy + lab[1] / 500.0) * 0.950_47;
+        let y = lab_f_inverse(fy);
+        let z = lab_f_inverse(fy - lab[2] / 200.0) * 1.088_83;
+        Color::from_linear_rgb_f32([
+            3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+            -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+            0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+        ])
+    }
+
+    /// The perceptual difference between two colors, as the distance between them in Oklab.
+    /// Alpha is ignored.
+    pub fn distance(self, other: Color) -> f32 {
+        oklab_distance_squared(self.to_oklab(), other.to_oklab()).sqrt()
+    }
+
+    /// Returns the index of the color in `palette` that looks closest to this one, or `None` if
+    /// the palette is empty. Use `palette::Palette` to search the same palette repeatedly.
+    pub fn nearest_index(self, palette: &[Color]) -> Option<usize> {
+        Palette::new(palette.to_vec()).nearest_index(self)
+    }
+
+    /// Creates an opaque color from a hue in degrees, and saturation and value between 0 and 1.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Color {
+        let mut color = Color::BLACK;
+        color.set_hsv(hue, saturation, value);
+        color
+    }
+
+    /// Creates an opaque color from a hue in degrees, and saturation and lightness between 0 and
+    /// 1.
+    pub fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> Color {
+        let mut color = Color::BLACK;
+        color.set_hsl(hue, saturation, lightness);
+        color
+    }
+
+    /// Returns the hue in degrees between 0 and 360, and the saturation and value between 0 and
+    /// 1. Grays have a hue of 0.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let [r, g, b] = self.to_rgb_f32();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+        (hue(r, g, b, max, min), saturation, max)
+    }
+
+    /// Returns the hue in degrees between 0 and 360, and the saturation and lightness between 0
+    /// and 1. Grays have a hue of 0.
+    pub fn to_hsl(self) -> (f32, f32, f32) {
+        let [r, g, b] = self.to_rgb_f32();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        (hue(r, g, b, max, min), saturation, lightness)
+    }
+
+    /// Sets the red, green and blue components from a hue in degrees, and saturation and value
+    /// between 0 and 1. Alpha is kept.
+    pub fn set_hsv(&mut self, hue: f32, saturation: f32, value: f32) {
+        let saturation = saturation.clamp(0.0, 1.0);
+        let value = value.clamp(0.0, 1.0);
+        let chroma = value * saturation;
+        self.set_hue_chroma(hue, chroma, value - chroma);
+    }
+
+    /// Sets the red, green and blue components from a hue in degrees, and saturation and
+    /// lightness between 0 and 1. Alpha is kept.
+    pub fn set_hsl(&mut self, hue: f32, saturation: f32, lightness: f32) {
+        let saturation = saturation.clamp(0.0, 1.0);
+        let lightness = lightness.clamp(0.0, 1.0);
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        self.set_hue_chroma(hue, chroma, lightness - chroma / 2.0);
+    }
+
+    pub fn hue(self) -> f32 {
+        self.to_hsv().0
+    }
+
+    pub fn set_hue(&mut self, hue: f32) {
+        let (_, saturation, value) = self.to_hsv();
+        self.set_hsv(hue, saturation, value);
+    }
+
+    /// The HSV saturation.
+    pub fn saturation(self) -> f32 {
+        self.to_hsv().1
+    }
+
+    /// Sets the HSV saturation.
+    pub fn set_saturation(&mut self, saturation: f32) {
+        let (hue, _, value) = self.to_hsv();
+        self.set_hsv(hue, saturation, value);
+    }
+
+    /// The HSV value.
+    pub fn value(self) -> f32 {
+        self.to_hsv().2
+    }
+
+    /// Sets the HSV value.
+    pub fn set_value(&mut self, value: f32) {
+        let (hue, saturation, _) = self.to_hsv();
+        self.set_hsv(hue, saturation, value);
+    }
+
+    /// Rotates the hue by `degrees`.
+    pub fn shift_hue(&mut self, degrees: f32) {
+        let (hue, saturation, value) = self.to_hsv();
+        self.set_hsv(hue + degrees, saturation, value);
+    }
+
+    /// Multiplies the HSV saturation and value, e.g. to desaturate and darken colors out of the
+    /// player's sight.
+    pub fn scale_hsv(&mut self, saturation_scale: f32, value_scale: f32) {
+        let (hue, saturation, value) = self.to_hsv();
+        self.set_hsv(hue, saturation * saturation_scale, value * value_scale);
+    }
+
+    /// Interpolates linearly between `self` at `t = 0` and `other` at `t = 1`, including alpha.
+    /// `t` is clamped between 0 and 1.
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color {
+            r: channel(self.r, other.r),
+            g: channel(self.g, other.g),
+            b: channel(self.b, other.b),
+            a: channel(self.a, other.a),
+        }
+    }
+
+    /// Multiplies the red, green and blue components by `factor`, saturating at 255. Alpha is
+    /// kept.
+    pub fn scale(self, factor: f32) -> Color {
+        let channel = |c: u8| (c as f32 * factor).round().clamp(0.0, 255.0) as u8;
+        Color { r: channel(self.r), g: channel(self.g), b: channel(self.b), a: self.a }
+    }
+
+    /// Composites `self` over `below` using their alpha, like painting with `self` on top of
+    /// `below`.
+    pub fn over(self, below: Color) -> Color {
+        let [r, g, b, a] = self.to_rgba_f32();
+        let [below_r, below_g, below_b, below_a] = below.to_rgba_f32();
+        let out_a = a + below_a * (1.0 - a);
+        if out_a == 0.0 {
+            return Color { r: 0, g: 0, b: 0, a: 0 };
+        }
+        let channel = |c: f32, below_c: f32| {
+            ((c * a + below_c * below_a * (1.0 - a)) / out_a * 255.0).round() as u8
+        };
+        Color {
+            r: channel(r, below_r),
+            g: channel(g, below_g),
+            b: channel(b, below_b),
+            a: (out_a * 255.0).round() as u8,
+        }
+    }
+
+    /// Adds `amount`, between -1 and 1, to the red, green and blue components. Alpha is kept.
+    pub fn adjust_brightness(self, amount: f32) -> Color {
+        let channel = |c: u8| (c as f32 + amount * 255.0).round().clamp(0.0, 255.0) as u8;
+        Color { r: channel(self.r), g: channel(self.g), b: channel(self.b), a: self.a }
+    }
+
+    /// Scales the distance of the red, green and blue components from mid-gray by `factor`, so
+    /// factors above 1 increase contrast and factors below 1 reduce it. Alpha is kept.
+    pub fn adjust_contrast(self, factor: f32) -> Color {
+        let channel = |c: u8| ((c as f32 - 127.5) * factor + 127.5).round().clamp(0.0, 255.0) as u8;
+        Color { r: channel(self.r), g: channel(self.g), b: channel(self.b), a: self.a }
+    }
+
+    fn set_hue_chroma(&mut self, hue: f32, chroma: f32, min: f32) {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let channel = |c: f32| ((c + min) * 255.0).round().clamp(0.0, 255.0) as u8;
+        self.r = channel(r);
+        self.g = channel(g);
+        self.b = channel(b);
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The nonlinear function of CIELAB.
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inverse(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA {
+        t * t * t
+    } else {
+        3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
+    }
+}
+
+pub(crate) fn oklab_distance_squared(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+/// The hue in degrees of a color with the given components, maximum and minimum.
+fn hue(r: f32, g: f32, b: f32, max: f32, min: f32) -> f32 {
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return 0.0;
+    }
+    let hue = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (hue * 60.0).rem_euclid(360.0)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseColorError {
+    Empty,
+    /// A `#` followed by a number of digits other than 3, 4, 6 or 8.
+    InvalidHexLength(String),
+    InvalidHexDigit(String),
+    /// An invalid `rgb(...)` or `rgba(...)` color, with the reason.
+    InvalidFunction(String, &'static str),
+    UnknownName(String),
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseColorError::Empty => write!(f, "empty color"),
+            ParseColorError::InvalidHexLength(s) => write!(
+                f,
+                "invalid color `{}`: expected 3, 4, 6 or 8 hex digits after `#`",
+                s,
+            ),
+            ParseColorError::InvalidHexDigit(s) => {
+                write!(f, "invalid color `{}`: expected only hex digits after `#`", s)
+            }
+            ParseColorError::InvalidFunction(s, reason) => {
+                write!(f, "invalid color `{}`: {}", s, reason)
+            }
+            ParseColorError::UnknownName(s) => write!(f, "unknown color name `{}`", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+/// Parses a color in one of the formats of CSS:
+///
+/// - hex: `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+/// - functions: `rgb(255, 128, 0)`, `rgba(255, 128, 0, 0.5)`, `rgb(100% 50% 0% / 50%)`
+/// - names: `cornflowerblue`, `transparent`, ignoring case and spaces so X11 names such as
+///   `Dark Slate Gray` work too
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Color, ParseColorError> {
+        let trimmed = s.trim();
+        if trimmed.is_empty() {
+            Err(ParseColorError::Empty)
+        } else if let Some(digits) = trimmed.strip_prefix('#') {
+            parse_hex(s, digits)
+        } else if let Some(open) = trimmed.find('(') {
+            parse_function(trimmed, open)
+                .map_err(|reason| ParseColorError::InvalidFunction(s.to_owned(), reason))
+        } else {
+            parse_name(trimmed).ok_or_else(|| ParseColorError::UnknownName(s.to_owned()))
+        }
+    }
+}
+
+fn parse_hex(s: &str, digits: &str) -> Result<Color, ParseColorError> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ParseColorError::InvalidHexDigit(s.to_owned()));
+    }
+    let digit = |i: usize| u8::from_str_radix(&digits[i..i + 1], 16).unwrap();
+    let pair = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    let rgba = match digits.len() {
+        3 => [digit(0) * 17, digit(1) * 17, digit(2) * 17, 255],
+        4 => [digit(0) * 17, digit(1) * 17, digit(2) * 17, digit(3) * 17],
+        6 => [pair(0), pair(2), pair(4), 255],
+        8 => [pair(0), pair(2), pair(4), pair(6)],
+        _ => return Err(ParseColorError::InvalidHexLength(s.to_owned())),
+    };
+    Ok(Color::from_rgba_u8(rgba))
+}
+
+fn parse_function(s: &str, open: usize) -> Result<Color, &'static str> {
+    let name = s[..open].trim().to_ascii_lowercase();
+    if name != "rgb" && name != "rgba" {
+        return Err("expected `rgb(...)` or `rgba(...)`");
+    }
+    let args = s[open + 1..]
+        .strip_suffix(')')
+        .ok_or("missing closing parenthesis")?;
+    // Both `rgb(r, g, b, a)` and `rgb(r g b / a)` are allowed.
+    let args: Vec<&str> = args
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    if args.len() != 3 && args.len() != 4 {
+        return Err("expected 3 or 4 components");
+    }
+    let channel = |arg: &str| -> Result<u8, &'static str> {
+        let value = match arg.strip_suffix('%') {
+            Some(percent) => parse_number(percent)? / 100.0 * 255.0,
+            None => parse_number(arg)?,
+        };
+        Ok(value.round().clamp(0.0, 255.0) as u8)
+    };
+    let alpha = match args.get(3) {
+        Some(arg) => {
+            let value = match arg.strip_suffix('%') {
+                Some(percent) => parse_number(percent)? / 100.0,
+                None => parse_number(arg)?,
+            };
+            (value * 255.0).round().clamp(0.0, 255.0) as u8
+        }
+        None => 255,
+    };
+    Ok(Color::from_rgba_u8([channel(args[0])?, channel(args[1])?, channel(args[2])?, alpha]))
+}
+
+fn parse_number(s: &str) -> Result<f32, &'static str> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or("components must be numbers or percentages")
+}
+
+fn parse_name(s: &str) -> Option<Color> {
+    let name: String = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if name == "transparent" {
+        return Some(Color { r: 0, g: 0, b: 0, a: 0 });
+    }
+    let index = CSS_COLORS.binary_search_by_key(&name.as_str(), |&(name, _)| name).ok()?;
+    let [r, g, b] = CSS_COLORS[index].1;
+    Some(Color { r, g, b, a: 255 })
+}
+
+/// Formats the color as `#rrggbb`, or `#rrggbbaa` if it isn't opaque.
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if self.a != 255 {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the red, green and blue components, saturating at 255. The alpha of the left color is
+/// kept.
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, other: Color) -> Color {
+        Color {
+            r: self.r.saturating_add(other.r),
+            g: self.g.saturating_add(other.g),
+            b: self.b.saturating_add(other.b),
+            a: self.a,
+        }
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, other: Color) {
+        *self = *self + other;
+    }
+}
+
+/// Subtracts the red, green and blue components, saturating at 0. The alpha of the left color is
+/// kept.
+impl Sub for Color {
+    type Output = Color;
+
+    fn sub(self, other: Color) -> Color {
+        Color {
+            r: self.r.saturating_sub(other.r),
+            g: self.g.saturating_sub(other.g),
+            b: self.b.saturating_sub(other.b),
+            a: self.a,
+        }
+    }
+}
+
+impl SubAssign for Color {
+    fn sub_assign(&mut self, other: Color) {
+        *self = *self - other;
+    }
+}
+
+/// Multiplies the red, green and blue components as if they were between 0 and 1, e.g. to light
+/// a color. The alpha of the left color is kept.
+impl Mul for Color {
+    type Output = Color;
+
+    fn mul(self, other: Color) -> Color {
+        let channel = |a: u8, b: u8| ((a as u32 * b as u32 + 127) / 255) as u8;
+        Color {
+            r: channel(self.r, other.r),
+            g: channel(self.g, other.g),
+            b: channel(self.b, other.b),
+            a: self.a,
+        }
+    }
+}
+
+impl MulAssign for Color {
+    fn mul_assign(&mut self, other: Color) {
+        *self = *self * other;
+    }
+}
+
+/// Same as `Color::scale`.
+impl Mul<f32> for Color {
+    type Output = Color;
+
+    fn mul(self, factor: f32) -> Color {
+        self.scale(factor)
+    }
+}
+
+impl MulAssign<f32> for Color {
+    fn mul_assign(&mut self, factor: f32) {
+        *self = self.scale(factor);
+    }
+}
+
+/// Colors interpolated between key colors at given positions, like libtcod's
+/// `TCODColor::genMap`. Useful for health bars, heat maps and light falloff.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawColorGradient"))]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColorGradient {
+    /// Sorted by position.
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorGradient {
+    pub fn new() -> ColorGradient {
+        ColorGradient { stops: Vec::new() }
+    }
+
+    /// Adds a key color at `position`. Positions can be any numbers, e.g. 0 to 1 or the indices
+    /// of a map.
+    pub fn add_stop(&mut self, position: f32, color: Color) {
+        let index = self.stops.iter().position(|&(stop_position, _)| stop_position > position);
+        self.stops.insert(index.unwrap_or(self.stops.len()), (position, color));
+    }
+
+    pub fn with_stop(mut self, position: f32, color: Color) -> ColorGradient {
+        self.add_stop(position, color);
+        self
+    }
+
+    /// The key colors, sorted by position.
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    /// Returns the color at `position`, interpolated between the closest stops. Positions before
+    /// the first stop or after the last one get the color of that stop. An empty gradient is
+    /// transparent black everywhere.
+    pub fn sample(&self, position: f32) -> Color {
+        let after = self.stops.iter().position(|&(stop_position, _)| stop_position > position);
+        match after {
+            None => self.stops.last().map_or(Color { r: 0, g: 0, b: 0, a: 0 }, |stop| stop.1),
+            Some(0) => self.stops[0].1,
+            Some(after) => {
+                let (start, start_color) = self.stops[after - 1];
+                let (end, end_color) = self.stops[after];
+                start_color.lerp(end_color, (position - start) / (end - start))
+            }
+        }
+    }
+
+    /// Samples the colors at positions 0 to `len - 1`, like `TCODColor::genMap` with stops at map
+    /// indices.
+    pub fn to_map(&self, len: usize) -> Vec<Color> {
+        (0..len).map(|i| self.sample(i as f32)).collect()
+    }
+}
+
+/// Creates a gradient from stops already sorted by position.
+impl TryFrom<Vec<(f32, Color)>> for ColorGradient {
+    type Error = GradientError;
+
+    fn try_from(stops: Vec<(f32, Color)>) -> Result<ColorGradient, GradientError> {
+        if stops.is_empty() {
+            return Err(GradientError::Empty);
+        }
+        let sorted = stops.windows(2).all(|pair| pair[0].0 <= pair[1].0);
+        if !sorted || stops.iter().any(|&(position, _)| position.is_nan()) {
+            return Err(GradientError::Unsorted);
+        }
+        Ok(ColorGradient { stops })
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawColorGradient {
+    stops: Vec<(f32, Color)>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawColorGradient> for ColorGradient {
+    type Error = GradientError;
+
+    fn try_from(raw: RawColorGradient) -> Result<ColorGradient, GradientError> {
+        ColorGradient::try_from(raw.stops)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientError {
+    /// No stops.
+    Empty,
+    /// Stops not sorted by position, or a position that is NaN.
+    Unsorted,
+}
+
+impl fmt::Display for GradientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GradientError::Empty => write!(f, "a color gradient needs at least one stop"),
+            GradientError::Unsorted => {
+                write!(f, "color gradient stops must be sorted by position")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GradientError {}
+
+impl FromIterator<(f32, Color)> for ColorGradient {
+    fn from_iter<I: IntoIterator<Item = (f32, Color)>>(iter: I) -> ColorGradient {
+        let mut gradient = ColorGradient::new();
+        for (position, color) in iter {
+            gradient.add_stop(position, color);
+        }
+        gradient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!("#f80".parse(), Ok(rgba(255, 136, 0, 255)));
+        assert_eq!("#f808".parse(), Ok(rgba(255, 136, 0, 136)));
+        assert_eq!("#FF8000".parse(), Ok(rgba(255, 128, 0, 255)));
+        assert_eq!(" #ff800080 ".parse(), Ok(rgba(255, 128, 0, 128)));
+    }
+
+    #[test]
+    fn parse_function() {
+        assert_eq!("rgb(255, 128, 0)".parse(), Ok(rgba(255, 128, 0, 255)));
+        assert_eq!("RGBA(255,128,0,0.5)".parse(), Ok(rgba(255, 128, 0, 128)));
+        assert_eq!("rgb(100% 50% 0% / 50%)".parse(), Ok(rgba(255, 128, 0, 128)));
+        // Out of range components are clamped.
+        assert_eq!("rgb(300, -5, 12.4, 2)".parse(), Ok(rgba(255, 0, 12, 255)));
+    }
+
+    #[test]
+    fn parse_name() {
+        assert_eq!("cornflowerblue".parse(), Ok(rgba(100, 149, 237, 255)));
+        assert_eq!("Dark Slate Gray".parse(), Ok(rgba(47, 79, 79, 255)));
+        assert_eq!("Transparent".parse(), Ok(rgba(0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for &color in &[rgba(1, 2, 3, 255), rgba(255, 128, 0, 7)] {
+            assert_eq!(color.to_string().parse(), Ok(color));
+        }
+        assert_eq!(rgba(255, 128, 0, 255).to_string(), "#ff8000");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |s: &str| s.parse::<Color>().unwrap_err();
+        assert_eq!(parse(" "), ParseColorError::Empty);
+        assert_eq!(parse("#12345"), ParseColorError::InvalidHexLength("#12345".into()));
+        assert_eq!(parse("#"), ParseColorError::InvalidHexLength("#".into()));
+        assert_eq!(parse("#ggg"), ParseColorError::InvalidHexDigit("#ggg".into()));
+        assert_eq!(parse("#éé"), ParseColorError::InvalidHexDigit("#éé".into()));
+        assert_eq!(parse("not a color"), ParseColorError::UnknownName("not a color".into()));
+        let functions = [
+            "hsl(0, 0, 0)",
+            "rgb(1, 2, 3",
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4, 5)",
+            "rgb(a, b, c)",
+            "rgb(inf, 0, 0)",
+        ];
+        for s in &functions {
+            assert!(matches!(parse(s), ParseColorError::InvalidFunction(..)), "{}", s);
+        }
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), rgba(255, 0, 0, 255));
+        assert_eq!(Color::from_hsv(120.0, 1.0, 1.0), rgba(0, 255, 0, 255));
+        assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0), rgba(0, 0, 255, 255));
+        assert_eq!(Color::from_hsv(480.0, 2.0, 0.5), rgba(0, 128, 0, 255));
+        for &color in &[rgba(255, 128, 0, 255), rgba(12, 200, 99, 255), rgba(70, 70, 70, 255)] {
+            let (hue, saturation, value) = color.to_hsv();
+            assert_eq!(Color::from_hsv(hue, saturation, value), color);
+        }
+        assert_eq!(rgba(70, 70, 70, 255).to_hsv().0, 0.0);
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        assert_eq!(Color::from_hsl(240.0, 1.0, 0.5), rgba(0, 0, 255, 255));
+        assert_eq!(Color::from_hsl(0.0, 0.0, 1.0), rgba(255, 255, 255, 255));
+        for &color in &[rgba(255, 128, 0, 255), rgba(12, 200, 99, 255), rgba(70, 70, 70, 255)] {
+            let (hue, saturation, lightness) = color.to_hsl();
+            assert_eq!(Color::from_hsl(hue, saturation, lightness), color);
+        }
+        let mut color = rgba(255, 0, 0, 9);
+        color.set_hsl(120.0, 1.0, 0.5);
+        assert_eq!(color, rgba(0, 255, 0, 9));
+    }
+
+    #[test]
+    fn lerp() {
+        let from = rgba(0, 100, 200, 0);
+        let to = rgba(255, 0, 100, 255);
+        assert_eq!(from.lerp(to, 0.0), from);
+        assert_eq!(from.lerp(to, 1.0), to);
+        assert_eq!(from.lerp(to, 0.5), rgba(128, 50, 150, 128));
+        assert_eq!(from.lerp(to, -1.0), from);
+        assert_eq!(from.lerp(to, 2.0), to);
+    }
+
+    #[test]
+    fn over() {
+        let red = rgba(255, 0, 0, 255);
+        let blue = rgba(0, 0, 255, 255);
+        let transparent = rgba(0, 0, 0, 0);
+        assert_eq!(red.over(blue), red);
+        assert_eq!(transparent.over(blue), blue);
+        assert_eq!(transparent.over(transparent), transparent);
+        assert_eq!(rgba(255, 0, 0, 128).over(blue), rgba(128, 0, 127, 255));
+        assert_eq!(rgba(255, 0, 0, 128).over(transparent), rgba(255, 0, 0, 128));
+    }
+
+    #[test]
+    fn gradient_sample() {
+        let red = rgba(255, 0, 0, 255);
+        let blue = rgba(0, 0, 255, 255);
+        let white = rgba(255, 255, 255, 255);
+        let gradient = ColorGradient::new()
+            .with_stop(10.0, white)
+            .with_stop(0.0, red)
+            .with_stop(4.0, blue);
+        assert_eq!(gradient.stops(), &[(0.0, red), (4.0, blue), (10.0, white)]);
+        assert_eq!(gradient.sample(-5.0), red);
+        assert_eq!(gradient.sample(0.0), red);
+        assert_eq!(gradient.sample(2.0), rgba(128, 0, 128, 255));
+        assert_eq!(gradient.sample(4.0), blue);
+        assert_eq!(gradient.sample(7.0), rgba(128, 128, 255, 255));
+        assert_eq!(gradient.sample(10.0), white);
+        assert_eq!(gradient.sample(99.0), white);
+        assert_eq!(gradient.to_map(3), [red, rgba(191, 0, 64, 255), rgba(128, 0, 128, 255)]);
+        assert_eq!(ColorGradient::new().sample(1.0), rgba(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn gradient_from_stops() {
+        let red = rgba(255, 0, 0, 255);
+        let stops = vec![(0.0, red), (1.0, red)];
+        assert_eq!(ColorGradient::try_from(stops.clone()).unwrap().stops(), &stops[..]);
+        assert_eq!(ColorGradient::try_from(vec![]), Err(GradientError::Empty));
+        let unsorted = vec![(1.0, red), (0.0, red)];
+        assert_eq!(ColorGradient::try_from(unsorted), Err(GradientError::Unsorted));
+        let nan = vec![(f32::NAN, red)];
+        assert_eq!(ColorGradient::try_from(nan), Err(GradientError::Unsorted));
+    }
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() <= tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn oklab() {
+        assert_close(Color::WHITE.to_oklab(), [1.0, 0.0, 0.0], 1e-3);
+        assert_close(Color::BLACK.to_oklab(), [0.0, 0.0, 0.0], 1e-3);
+        assert_close(rgba(255, 0, 0, 255).to_oklab(), [0.628, 0.2249, 0.1258], 1e-3);
+        for &color in &[rgba(255, 128, 0, 255), rgba(12, 200, 99, 255), rgba(70, 70, 70, 255)] {
+            assert_eq!(Color::from_oklab(color.to_oklab()), color);
+        }
+        // Out of gamut colors are clipped and transparency is dropped.
+        assert_eq!(Color::from_oklab([2.0, 0.0, 0.0]), Color::WHITE);
+        assert_eq!(Color::from_oklab(rgba(1, 2, 3, 0).to_oklab()), rgba(1, 2, 3, 255));
+    }
+
+    #[test]
+    fn lab() {
+        assert_close(Color::WHITE.to_lab(), [100.0, 0.0, 0.0], 0.05);
+        assert_close(Color::BLACK.to_lab(), [0.0, 0.0, 0.0], 0.05);
+        assert_close(rgba(255, 0, 0, 255).to_lab(), [53.24, 80.09, 67.20], 0.05);
+        for &color in &[rgba(255, 128, 0, 255), rgba(12, 200, 99, 255), rgba(70, 70, 70, 255)] {
+            assert_eq!(Color::from_lab(color.to_lab()), color);
+        }
+        assert_eq!(Color::from_lab([-10.0, 0.0, 0.0]), Color::BLACK);
+    }
+
+    #[test]
+    fn nearest_index() {
+        let palette = [Color::BLACK, Color::WHITE, rgba(255, 0, 0, 255)];
+        assert_eq!(rgba(20, 20, 20, 255).nearest_index(&palette), Some(0));
+        assert_eq!(rgba(200, 30, 20, 255).nearest_index(&palette), Some(2));
+        assert_eq!(Color::WHITE.nearest_index(&[]), None);
+        assert_eq!(Color::WHITE.distance(Color::WHITE), 0.0);
+    }
+}
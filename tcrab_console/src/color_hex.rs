@@ -0,0 +1,16 @@
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::Color;
+
+/// Serializes a color as a hex string such as `"#ff8000"`, instead of a structure. Use with
+/// `#[serde(with = "tcrab_console::color_hex")]`.
+pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(color)
+}
+
+/// Deserializes a color from any string accepted by `Color::from_str`, such as `"#ff8000"` or
+/// `"orange"`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::canvas::{Attributes, Cell, CellBuffer, CustomGlyph, Glyph, UserData};
+use crate::{Canvas, Color};
+
+/// The first bytes of every encoded buffer.
+const MAGIC: &[u8; 4] = b"TCCB";
+
+/// The version written by `encode`. Every version can be read by later releases.
+pub const FORMAT_VERSION: u16 = 1;
+
+const GLYPH_CHAR: u8 = 0;
+const GLYPH_CUSTOM: u8 = 1;
+const GLYPH_CONTINUATION: u8 = 2;
+
+/// A custom glyph that can be stored in the compact format, as a numeric id.
+///
+/// Ids are written to save files, so they must not change between versions of a game.
+pub trait CompactGlyph: CustomGlyph {
+    fn to_id(self) -> u32;
+    fn from_id(id: u32) -> Option<Self>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactError {
+    /// The data doesn't start with the format's header.
+    InvalidHeader,
+    /// The data was written by a later version of this crate.
+    UnsupportedVersion(u16),
+    /// The data is truncated or otherwise damaged.
+    Corrupt(&'static str),
+    /// `CompactGlyph::from_id` returned `None`.
+    UnknownCustomGlyph(u32),
+    /// The buffer has more cells than the format's 32-bit sizes can hold. Only returned by
+    /// `encode`.
+    TooLarge,
+}
+
+impl std::fmt::Display for CompactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CompactError::InvalidHeader => write!(f, "not a compact cell buffer"),
+            CompactError::UnsupportedVersion(version) => {
+                write!(f, "unsupported compact cell buffer version {}", version)
+            }
+            CompactError::Corrupt(message) => write!(f, "corrupt compact cell buffer: {}", message),
+            CompactError::UnknownCustomGlyph(id) => write!(f, "unknown custom glyph id {}", id),
+            CompactError::TooLarge => write!(f, "cell buffer too large for the compact format"),
+        }
+    }
+}
+
+impl std::error::Error for CompactError {}
+
+/// Encodes a buffer in a compact binary format, much smaller than its serde representation.
+///
+/// The distinct cells of the buffer are stored once in a palette, followed by runs of identical
+/// cells in row order. The format starts with a header holding its version, so data written by
+/// earlier releases keeps decoding.
+pub fn encode<C: CompactGlyph>(buffer: &CellBuffer<C>) -> Result<Vec<u8>, CompactError> {
+    let (width, height) = buffer.size();
+
+    let mut palette = Vec::new();
+    let mut palette_indices = HashMap::new();
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let cell = buffer.get_cell(x, y);
+            let index = *palette_indices.entry(cell).or_insert_with(|| {
+                palette.push(cell);
+                palette.len() - 1
+            });
+            match runs.last_mut() {
+                Some((run_index, run_length)) if *run_index == index => *run_length += 1,
+                _ => runs.push((index, 1)),
+            }
+        }
+    }
+
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    write_var_len(&mut data, width)?;
+    write_var_len(&mut data, height)?;
+    write_var_len(&mut data, palette.len())?;
+    for cell in &palette {
+        match cell.glyph {
+            Glyph::Char(c) => {
+                data.push(GLYPH_CHAR);
+                write_var_u32(&mut data, c as u32);
+            }
+            Glyph::Custom(glyph) => {
+                data.push(GLYPH_CUSTOM);
+                write_var_u32(&mut data, glyph.to_id());
+            }
+            Glyph::Continuation => data.push(GLYPH_CONTINUATION),
+        }
+        for color in &[cell.foreground_color, cell.background_color] {
+            data.extend_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+        let user_data = cell.user_data;
+        data.push(user_data.flags);
+        data.extend_from_slice(&user_data.params);
+        data.push(cell.attributes.bits());
+    }
+    write_var_len(&mut data, runs.len())?;
+    for (index, length) in runs {
+        write_var_len(&mut data, index)?;
+        write_var_len(&mut data, length)?;
+    }
+    Ok(data)
+}
+
+/// Decodes a buffer written by `encode`.
+pub fn decode<C: CompactGlyph>(mut data: &[u8]) -> Result<CellBuffer<C>, CompactError> {
+    let data = &mut data;
+    if take_bytes(data, 4).ok() != Some(&MAGIC[..]) {
+        return Err(CompactError::InvalidHeader);
+    }
+    let version_bytes = take_bytes(data, 2).map_err(|_| CompactError::InvalidHeader)?;
+    let version = u16::from_le_bytes([version_bytes[0], version_bytes[1]]);
+    if version > FORMAT_VERSION {
+        return Err(CompactError::UnsupportedVersion(version));
+    }
+
+    let width = read_var_u32(data)? as usize;
+    let height = read_var_u32(data)? as usize;
+    let palette_len = read_var_u32(data)? as usize;
+    let mut palette = Vec::with_capacity(palette_len.min(data.len()));
+    for _ in 0..palette_len {
+        let glyph = match take_bytes(data, 1)?[0] {
+            GLYPH_CHAR => {
+                let c = std::char::from_u32(read_var_u32(data)?)
+                    .ok_or(CompactError::Corrupt("invalid character"))?;
+                Glyph::Char(c)
+            }
+            GLYPH_CUSTOM => {
+                let id = read_var_u32(data)?;
+                Glyph::Custom(C::from_id(id).ok_or(CompactError::UnknownCustomGlyph(id))?)
+            }
+            GLYPH_CONTINUATION => Glyph::Continuation,
+            _ => return Err(CompactError::Corrupt("invalid glyph kind")),
+        };
+        let bytes = take_bytes(data, 13)?;
+        palette.push(Cell {
+            glyph,
+            foreground_color: Color::from_rgba_u8([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            background_color: Color::from_rgba_u8([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            attributes: Attributes::from_bits_truncate(bytes[12]),
+            user_data: UserData { flags: bytes[8], params: [bytes[9], bytes[10], bytes[11]] },
+        });
+    }
+
+    // The runs are read before the buffer is allocated, so damaged sizes are rejected first.
+    let cell_count = width.checked_mul(height).ok_or(CompactError::Corrupt("buffer too large"))?;
+    let run_count = read_var_u32(data)? as usize;
+    // Every run takes at least two bytes.
+    let mut runs = Vec::with_capacity(run_count.min(data.len() / 2));
+    let mut total = 0usize;
+    for _ in 0..run_count {
+        let cell = *palette
+            .get(read_var_u32(data)? as usize)
+            .ok_or(CompactError::Corrupt("palette index out of range"))?;
+        let length = read_var_u32(data)? as usize;
+        total = match total.checked_add(length) {
+            Some(total) if total <= cell_count => total,
+            _ => return Err(CompactError::Corrupt("too many cells")),
+        };
+        runs.push((cell, length));
+    }
+    if total != cell_count {
+        return Err(CompactError::Corrupt("too few cells"));
+    }
+
+    let mut buffer = CellBuffer::new(width, height, Cell::default());
+    let mut position = 0;
+    for (cell, length) in runs {
+        for i in position..position + length {
+            buffer.set_cell(i % width, i / width, cell);
+        }
+        position += length;
+    }
+    Ok(buffer)
+}
+
+/// Serializes a buffer in the compact format, as bytes. Use with
+/// `#[serde(with = "tcrab_console::compact")]`.
+#[cfg(feature = "serde")]
+pub fn serialize<C, S>(buffer: &CellBuffer<C>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    C: CompactGlyph,
+    S: serde::Serializer,
+{
+    serializer.serialize_bytes(&encode(buffer).map_err(serde::ser::Error::custom)?)
+}
+
+/// Deserializes a buffer serialized by `serialize`.
+#[cfg(feature = "serde")]
+pub fn deserialize<'de, C, D>(deserializer: D) -> Result<CellBuffer<C>, D::Error>
+where
+    C: CompactGlyph,
+    D: serde::Deserializer<'de>,
+{
+    struct BytesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "a compact cell buffer")
+        }
+
+        fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: serde::de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        // Formats without a byte type, such as JSON, store bytes as a sequence.
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+    let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+    decode(&bytes).map_err(serde::de::Error::custom)
+}
+
+/// Writes a LEB128 variable length integer: 7 bits per byte, lowest first, with the high bit set
+/// on all but the last byte.
+fn write_var_u32(data: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+/// Writes a size or index as a variable length integer, failing if it doesn't fit in 32 bits.
+fn write_var_len(data: &mut Vec<u8>, value: usize) -> Result<(), CompactError> {
+    write_var_u32(data, u32::try_from(value).map_err(|_| CompactError::TooLarge)?);
+    Ok(())
+}
+
+fn read_var_u32(data: &mut &[u8]) -> Result<u32, CompactError> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = take_bytes(data, 1)?[0];
+        value |= ((byte & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(CompactError::Corrupt("integer too long"))
+}
+
+fn take_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], CompactError> {
+    if data.len() < len {
+        return Err(CompactError::Corrupt("unexpected end of data"));
+    }
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Wall(u32);
+
+    impl CustomGlyph for Wall {}
+
+    impl CompactGlyph for Wall {
+        fn to_id(self) -> u32 {
+            self.0
+        }
+
+        fn from_id(id: u32) -> Option<Wall> {
+            if id < 10 {
+                Some(Wall(id))
+            } else {
+                None
+            }
+        }
+    }
+
+    fn header(width: u32, height: u32) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        write_var_u32(&mut data, width);
+        write_var_u32(&mut data, height);
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = CellBuffer::new(5, 3, Cell::default());
+        let mut cell = Cell {
+            glyph: Glyph::Char('@'),
+            foreground_color: Color::from_rgba_u8([255, 128, 0, 200]),
+            background_color: Color::BLACK,
+            attributes: Attributes::BOLD | Attributes::UNDERLINE,
+            user_data: UserData { flags: 3, params: [1, 2, 3] },
+        };
+        buffer.set_cell(1, 0, cell);
+        cell.glyph = Glyph::Custom(Wall(7));
+        buffer.set_cell(4, 2, cell);
+        buffer.set_cell(0, 1, Cell { glyph: Glyph::Continuation, ..Cell::default() });
+
+        let decoded = decode::<Wall>(&encode(&buffer).unwrap()).unwrap();
+        assert_eq!(decoded, buffer);
+    }
+
+    #[test]
+    fn round_trip_empty() {
+        let buffer = CellBuffer::<Wall>::new(0, 4, Cell::default());
+        assert_eq!(decode::<Wall>(&encode(&buffer).unwrap()).unwrap(), buffer);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let mut buffer = CellBuffer::new(4, 4, Cell::default());
+        buffer.set_cell(2, 2, Cell { glyph: Glyph::Custom(Wall(1)), ..Cell::default() });
+        let data = encode(&buffer).unwrap();
+        for len in 0..data.len() {
+            assert!(decode::<Wall>(&data[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        assert_eq!(decode::<Wall>(b"nope").unwrap_err(), CompactError::InvalidHeader);
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            decode::<Wall>(&data).unwrap_err(),
+            CompactError::UnsupportedVersion(FORMAT_VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn rejects_huge_size_before_allocating() {
+        let mut data = header(u32::MAX, u32::MAX);
+        write_var_u32(&mut data, 0);
+        write_var_u32(&mut data, 0);
+        assert!(matches!(decode::<Wall>(&data), Err(CompactError::Corrupt(_))));
+
+        // One run claiming all cells of a buffer too large to allocate.
+        let mut data = header(1 << 16, 1 << 16);
+        write_var_u32(&mut data, 1);
+        data.extend_from_slice(&[GLYPH_CHAR, b'a', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        write_var_u32(&mut data, 2);
+        write_var_u32(&mut data, 0);
+        write_var_u32(&mut data, u32::MAX);
+        write_var_u32(&mut data, 0);
+        write_var_u32(&mut data, 2);
+        assert_eq!(decode::<Wall>(&data).unwrap_err(), CompactError::Corrupt("too many cells"));
+    }
+
+    #[test]
+    fn rejects_wrong_cell_counts() {
+        let mut data = header(2, 2);
+        write_var_u32(&mut data, 1);
+        data.extend_from_slice(&[GLYPH_CHAR, b'a', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut too_few = data.clone();
+        write_var_u32(&mut too_few, 1);
+        write_var_u32(&mut too_few, 0);
+        write_var_u32(&mut too_few, 3);
+        assert_eq!(decode::<Wall>(&too_few).unwrap_err(), CompactError::Corrupt("too few cells"));
+
+        let mut too_many = data.clone();
+        write_var_u32(&mut too_many, 1);
+        write_var_u32(&mut too_many, 0);
+        write_var_u32(&mut too_many, 5);
+        assert_eq!(decode::<Wall>(&too_many).unwrap_err(), CompactError::Corrupt("too many cells"));
+
+        let mut bad_index = data;
+        write_var_u32(&mut bad_index, 1);
+        write_var_u32(&mut bad_index, 1);
+        write_var_u32(&mut bad_index, 4);
+        assert_eq!(
+            decode::<Wall>(&bad_index).unwrap_err(),
+            CompactError::Corrupt("palette index out of range")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_custom_glyph() {
+        let buffer =
+            CellBuffer::new(1, 1, Cell { glyph: Glyph::Custom(Wall(12)), ..Cell::default() });
+        assert_eq!(
+            decode::<Wall>(&encode(&buffer).unwrap()).unwrap_err(),
+            CompactError::UnknownCustomGlyph(12)
+        );
+    }
+
+    #[test]
+    fn rejects_buffers_too_large_to_encode() {
+        // Empty, so it takes no memory, but too wide for the format's 32-bit width.
+        let buffer = CellBuffer::<Wall>::new(u32::MAX as usize + 1, 0, Cell::default());
+        assert_eq!(encode(&buffer).unwrap_err(), CompactError::TooLarge);
+    }
+}
@@ -1,8 +1,21 @@
 mod color;
+#[cfg(feature = "serde")]
+pub mod color_hex;
+mod css_colors;
+pub mod ansi;
+pub mod asciicast;
 pub mod canvas;
+pub mod compact;
+pub mod cp437;
 pub mod event;
+pub mod libtcod;
+mod named_colors;
+pub mod palette;
+#[cfg(feature = "rexpaint")]
+pub mod rexpaint;
+pub mod tween;
 
-pub use self::color::Color;
+pub use self::color::{Color, ColorGradient, GradientError, ParseColorError};
 pub use self::canvas::Canvas;
 pub use self::event::Event;
 
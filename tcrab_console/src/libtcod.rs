@@ -0,0 +1,413 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::canvas::{Cell, CellBuffer, CustomGlyph, Glyph};
+use crate::{cp437, Canvas, Color};
+
+/// The version written by `TCODConsole::saveAsc`.
+const ASC_VERSION: &str = "0.3";
+
+#[derive(Debug)]
+pub enum LibtcodError {
+    Io(io::Error),
+    /// The file is not a valid libtcod console file.
+    Format(&'static str),
+    /// A cell's glyph has no CP437 code, e.g. a custom glyph. Only returned when writing.
+    UnencodableGlyph {
+        x: usize,
+        y: usize,
+    },
+}
+
+impl std::fmt::Display for LibtcodError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LibtcodError::Io(err) => write!(f, "{}", err),
+            LibtcodError::Format(message) => write!(f, "invalid console file: {}", message),
+            LibtcodError::UnencodableGlyph { x, y } => {
+                write!(f, "glyph at ({}, {}) has no CP437 code", x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LibtcodError {}
+
+impl From<io::Error> for LibtcodError {
+    fn from(err: io::Error) -> LibtcodError {
+        LibtcodError::Io(err)
+    }
+}
+
+/// Reads a console saved with libtcod's `TCODConsole::saveAsc`. Glyph codes are translated from
+/// CP437.
+pub fn read_asc<C: CustomGlyph, R: Read>(mut reader: R) -> Result<CellBuffer<C>, LibtcodError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let header_end = data.iter().position(|&b| b == b'#')
+        .ok_or(LibtcodError::Format("missing start of cell data"))?;
+    let header = std::str::from_utf8(&data[..header_end])
+        .map_err(|_| LibtcodError::Format("invalid header"))?;
+    let mut tokens = header.split_whitespace();
+    if tokens.next() != Some("ASCII-Paint") {
+        return Err(LibtcodError::Format("not an ASCII-Paint file"));
+    }
+    let version: f32 = tokens.next()
+        .and_then(|token| token.strip_prefix('v'))
+        .and_then(|version| version.parse().ok())
+        .ok_or(LibtcodError::Format("invalid version"))?;
+    let mut next_size = || tokens.next()
+        .and_then(|token| token.parse::<usize>().ok())
+        .ok_or(LibtcodError::Format("invalid console size"));
+    let width = next_size()?;
+    let height = next_size()?;
+
+    // Since version 0.3, each cell ends with two bytes for whether it's solid and walkable.
+    let cell_size = if version >= 0.3 { 9 } else { 7 };
+    let cells = &data[header_end + 1..];
+    check_cell_data(cells, width, height, cell_size, "unexpected end of file")?;
+    let mut buffer = CellBuffer::new(width, height, Cell::default());
+    // Cells are stored column by column.
+    for (i, cell) in cells.chunks(cell_size).take(width * height).enumerate() {
+        buffer.set_cell(i / height, i % height, decode_cell(cell));
+    }
+    Ok(buffer)
+}
+
+/// Writes a console the way libtcod's `TCODConsole::saveAsc` does. Continuation cells are written
+/// as spaces.
+pub fn write_asc<C, W>(mut writer: W, buffer: &CellBuffer<C>) -> Result<(), LibtcodError>
+where
+    C: CustomGlyph,
+    W: Write,
+{
+    let (width, height) = buffer.size();
+    write!(writer, "ASCII-Paint v{}\n{} {}\n#", ASC_VERSION, width, height)?;
+    let mut data = Vec::with_capacity(width * height * 9);
+    for x in 0..width {
+        for y in 0..height {
+            encode_cell(&mut data, buffer, x, y)?;
+            // Not solid, walkable.
+            data.extend_from_slice(&[0, 1]);
+        }
+    }
+    writer.write_all(&data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_asc<C: CustomGlyph, P: AsRef<Path>>(path: P) -> Result<CellBuffer<C>, LibtcodError> {
+    read_asc(BufReader::new(File::open(path)?))
+}
+
+pub fn save_asc<C, P>(path: P, buffer: &CellBuffer<C>) -> Result<(), LibtcodError>
+where
+    C: CustomGlyph,
+    P: AsRef<Path>,
+{
+    write_asc(BufWriter::new(File::create(path)?), buffer)
+}
+
+/// Reads a console saved with libtcod's `TCODConsole::saveApf`, a RIFF file made for the
+/// ASCII-Paint editor. Only the first layer is read, like libtcod does. Glyph codes are translated
+/// from CP437.
+pub fn read_apf<C: CustomGlyph, R: Read>(mut reader: R) -> Result<CellBuffer<C>, LibtcodError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut data = &data[..];
+
+    if read_four_cc(&mut data)? != *b"RIFF" {
+        return Err(LibtcodError::Format("not a RIFF file"));
+    }
+    let riff_size = read_u32(&mut data)? as usize;
+    let mut riff = take_bytes(&mut data, riff_size)?;
+    if read_four_cc(&mut riff)? != *b"apf " {
+        return Err(LibtcodError::Format("not an ASCII-Paint file"));
+    }
+
+    let mut size = None;
+    let mut cells = None;
+    // Lists are flattened into a stack of chunks still to be read.
+    let mut lists = vec![riff];
+    while let Some(mut list) = lists.pop() {
+        while !list.is_empty() {
+            let id = read_four_cc(&mut list)?;
+            let chunk_size = read_u32(&mut list)? as usize;
+            let mut chunk = take_bytes(&mut list, chunk_size)?;
+            match &id {
+                b"LIST" => {
+                    // The list type, e.g. "fset", "imgd" or "layr".
+                    read_four_cc(&mut chunk)?;
+                    lists.push(list);
+                    list = chunk;
+                }
+                b"img " => {
+                    let width = read_u32(&mut chunk)? as usize;
+                    let height = read_u32(&mut chunk)? as usize;
+                    let filter = read_u32(&mut chunk)?;
+                    let format = read_u32(&mut chunk)?;
+                    if (filter, format) != (0, 0) {
+                        return Err(LibtcodError::Format("unsupported image encoding"));
+                    }
+                    size = Some((width, height));
+                }
+                b"ldat" if cells.is_none() => cells = Some(chunk),
+                _ => {}
+            }
+        }
+    }
+
+    let (width, height) = size.ok_or(LibtcodError::Format("missing image details"))?;
+    let cells = cells.ok_or(LibtcodError::Format("missing layer data"))?;
+    check_cell_data(cells, width, height, 7, "layer data too short")?;
+    let mut buffer = CellBuffer::new(width, height, Cell::default());
+    // Cells are stored column by column.
+    for (i, cell) in cells.chunks(7).take(width * height).enumerate() {
+        buffer.set_cell(i / height, i % height, decode_cell(cell));
+    }
+    Ok(buffer)
+}
+
+/// Writes a console the way libtcod's `TCODConsole::saveApf` does. Continuation cells are written
+/// as spaces.
+pub fn write_apf<C, W>(mut writer: W, buffer: &CellBuffer<C>) -> Result<(), LibtcodError>
+where
+    C: CustomGlyph,
+    W: Write,
+{
+    let (width, height) = buffer.size();
+
+    let mut settings = Vec::new();
+    // Grid shown, 8 by 8 pixels.
+    write_chunk(&mut settings, b"fv1 ", &u32_bytes(&[1, 8, 8]));
+
+    let mut cells = Vec::with_capacity(width * height * 7);
+    for x in 0..width {
+        for y in 0..height {
+            encode_cell(&mut cells, buffer, x, y)?;
+        }
+    }
+    let mut layers = Vec::new();
+    // Name, blend mode, foreground alpha, background alpha, visible, index and data size.
+    let layer = u32_bytes(&[0, 0, 255, 255, 1, 0, cells.len() as u32]);
+    write_chunk(&mut layers, b"lyr2", &layer);
+    write_chunk(&mut layers, b"ldat", &cells);
+
+    let mut image = Vec::new();
+    // Size, no filter and the character, foreground, background format.
+    write_chunk(&mut image, b"img ", &u32_bytes(&[width as u32, height as u32, 0, 0]));
+    write_list(&mut image, b"layr", &layers);
+
+    let mut riff = b"apf ".to_vec();
+    write_list(&mut riff, b"fset", &settings);
+    write_list(&mut riff, b"imgd", &image);
+
+    let mut file = Vec::new();
+    write_chunk(&mut file, b"RIFF", &riff);
+    writer.write_all(&file)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_apf<C: CustomGlyph, P: AsRef<Path>>(path: P) -> Result<CellBuffer<C>, LibtcodError> {
+    read_apf(BufReader::new(File::open(path)?))
+}
+
+pub fn save_apf<C, P>(path: P, buffer: &CellBuffer<C>) -> Result<(), LibtcodError>
+where
+    C: CustomGlyph,
+    P: AsRef<Path>,
+{
+    write_apf(BufWriter::new(File::create(path)?), buffer)
+}
+
+/// Checks that `cells` holds `width` by `height` cells of `cell_size` bytes, before a buffer of
+/// that size is allocated.
+fn check_cell_data(
+    cells: &[u8],
+    width: usize,
+    height: usize,
+    cell_size: usize,
+    message: &'static str,
+) -> Result<(), LibtcodError> {
+    match width.checked_mul(height).and_then(|count| count.checked_mul(cell_size)) {
+        Some(len) if len <= cells.len() => Ok(()),
+        _ => Err(LibtcodError::Format(message)),
+    }
+}
+
+/// Decodes a character code followed by the foreground and background colors.
+fn decode_cell<C: CustomGlyph>(data: &[u8]) -> Cell<C> {
+    Cell {
+        glyph: Glyph::Char(cp437::to_char(data[0])),
+        foreground_color: Color { r: data[1], g: data[2], b: data[3], a: 255 },
+        background_color: Color { r: data[4], g: data[5], b: data[6], a: 255 },
+        ..Cell::default()
+    }
+}
+
+fn encode_cell<C: CustomGlyph>(
+    data: &mut Vec<u8>,
+    buffer: &CellBuffer<C>,
+    x: usize,
+    y: usize,
+) -> Result<(), LibtcodError> {
+    let cell = buffer.get_cell(x, y);
+    let code = match cell.glyph {
+        Glyph::Char(c) => cp437::from_char(c),
+        Glyph::Custom(_) => None,
+        Glyph::Continuation => Some(b' '),
+    };
+    let code = code.ok_or(LibtcodError::UnencodableGlyph { x, y })?;
+    let (foreground, background) = (cell.foreground_color, cell.background_color);
+    data.extend_from_slice(&[
+        code,
+        foreground.r, foreground.g, foreground.b,
+        background.r, background.g, background.b,
+    ]);
+    Ok(())
+}
+
+fn take_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], LibtcodError> {
+    if data.len() < len {
+        return Err(LibtcodError::Format("unexpected end of file"));
+    }
+    let (bytes, rest) = data.split_at(len);
+    *data = rest;
+    Ok(bytes)
+}
+
+fn read_four_cc(data: &mut &[u8]) -> Result<[u8; 4], LibtcodError> {
+    let mut four_cc = [0; 4];
+    four_cc.copy_from_slice(take_bytes(data, 4)?);
+    Ok(four_cc)
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<u32, LibtcodError> {
+    read_four_cc(data).map(u32::from_le_bytes)
+}
+
+fn u32_bytes(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes().to_vec()).collect()
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+fn write_list(out: &mut Vec<u8>, list_type: &[u8; 4], chunks: &[u8]) {
+    let mut list = list_type.to_vec();
+    list.extend_from_slice(chunks);
+    write_chunk(out, b"LIST", &list);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl CustomGlyph for Tile {}
+
+    fn buffer() -> CellBuffer<Tile> {
+        let mut buffer = CellBuffer::new(3, 2, Cell::default());
+        buffer.set_cell(2, 1, Cell {
+            glyph: Glyph::Char('♥'),
+            foreground_color: Color { r: 1, g: 2, b: 3, a: 255 },
+            background_color: Color { r: 4, g: 5, b: 6, a: 255 },
+            ..Cell::default()
+        });
+        buffer.set_cell(0, 1, Cell { glyph: Glyph::Char('@'), ..Cell::default() });
+        buffer
+    }
+
+    fn is_format_error<T>(result: Result<T, LibtcodError>) -> bool {
+        matches!(result, Err(LibtcodError::Format(_)))
+    }
+
+    #[test]
+    fn asc_round_trip() {
+        let mut data = Vec::new();
+        write_asc(&mut data, &buffer()).unwrap();
+        assert!(data.starts_with(b"ASCII-Paint v0.3\n3 2\n#"));
+        assert_eq!(read_asc::<Tile, _>(&data[..]).unwrap(), buffer());
+    }
+
+    #[test]
+    fn asc_old_version() {
+        // Before version 0.3, cells have no solid and walkable bytes.
+        let mut data = b"ASCII-Paint v0.2\n1 2\n#".to_vec();
+        data.extend_from_slice(&[b'a', 1, 2, 3, 4, 5, 6, b'b', 0, 0, 0, 0, 0, 0]);
+        let buffer = read_asc::<Tile, _>(&data[..]).unwrap();
+        assert_eq!(buffer.get_cell(0, 0).foreground_color, Color { r: 1, g: 2, b: 3, a: 255 });
+        assert_eq!(buffer.get_cell(0, 1).glyph, Glyph::Char('b'));
+    }
+
+    #[test]
+    fn asc_malformed() {
+        let cases: [&[u8]; 7] = [
+            b"ASCII-Paint v0.3\n1 1\n",
+            b"Paint v0.3\n1 1\n#",
+            b"ASCII-Paint 0.3\n1 1\n#",
+            b"ASCII-Paint v0.3\n1\n#",
+            b"ASCII-Paint v0.3\n1 1\n#abcdefgh",
+            b"ASCII-Paint v0.3\n18446744073709551615 18446744073709551615\n#",
+            b"ASCII-Paint v0.3\n4294967296 4294967296\n#",
+        ];
+        for data in &cases {
+            assert!(is_format_error(read_asc::<Tile, _>(*data)));
+        }
+    }
+
+    #[test]
+    fn apf_round_trip() {
+        let mut data = Vec::new();
+        write_apf(&mut data, &buffer()).unwrap();
+        assert!(data.starts_with(b"RIFF"));
+        assert_eq!(read_apf::<Tile, _>(&data[..]).unwrap(), buffer());
+    }
+
+    /// An apf file whose image is `width` by `height` cells, with `ldat` as its layer data.
+    fn apf(width: u32, height: u32, encoding: u32, ldat: Option<&[u8]>) -> Vec<u8> {
+        let mut image = Vec::new();
+        write_chunk(&mut image, b"img ", &u32_bytes(&[width, height, encoding, 0]));
+        if let Some(ldat) = ldat {
+            write_chunk(&mut image, b"ldat", ldat);
+        }
+        let mut riff = b"apf ".to_vec();
+        write_list(&mut riff, b"imgd", &image);
+        let mut file = Vec::new();
+        write_chunk(&mut file, b"RIFF", &riff);
+        file
+    }
+
+    #[test]
+    fn apf_malformed() {
+        assert!(read_apf::<Tile, _>(&apf(1, 1, 0, Some(&[b'x'; 7]))[..]).is_ok());
+        let cases = [
+            apf(1, 1, 0, Some(&[b'x'; 6])),
+            apf(1, 1, 0, None),
+            apf(1, 1, 1, Some(&[b'x'; 7])),
+            apf(u32::MAX, u32::MAX, 0, Some(&[b'x'; 7])),
+            apf(1, 1, 0, Some(&[b'x'; 7]))[..20].to_vec(),
+            b"RIFX\0\0\0\0".to_vec(),
+        ];
+        for data in &cases {
+            assert!(is_format_error(read_apf::<Tile, _>(&data[..])));
+        }
+    }
+
+    #[test]
+    fn unencodable_glyph() {
+        let buffer = CellBuffer::new(1, 2, Cell { glyph: Glyph::Custom(Tile), ..Cell::default() });
+        assert!(matches!(
+            write_asc(Vec::new(), &buffer),
+            Err(LibtcodError::UnencodableGlyph { x: 0, y: 0 })
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use crate::ansi::{palette_256_color, ANSI_COLORS};
+use crate::canvas::CustomGlyph;
+use crate::color::oklab_distance_squared;
+use crate::{Canvas, Color};
+
+/// A fixed set of colors, for finding the closest one to any color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<Color>,
+    /// The colors in Oklab, where distances match perceived differences.
+    oklab: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Palette {
+        let oklab = colors.iter().map(|color| color.to_oklab()).collect();
+        Palette { colors, oklab }
+    }
+
+    /// The 16 colors of terminals without 256 color support, in the order of their SGR codes.
+    pub fn ansi_16() -> Palette {
+        Palette::new(ANSI_COLORS.to_vec())
+    }
+
+    /// The 256 colors of xterm and most terminals, in the order of their `38;5;n` SGR codes.
+    pub fn xterm_256() -> Palette {
+        Palette::new((0..=255).map(palette_256_color).collect())
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Returns the index of the color that looks closest to `color`, or `None` if the palette is
+    /// empty.
+    pub fn nearest_index(&self, color: Color) -> Option<usize> {
+        let lab = color.to_oklab();
+        self.oklab
+            .iter()
+            .map(|palette_lab| oklab_distance_squared(lab, *palette_lab))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the color that looks closest to `color`, keeping its alpha. Returns `color`
+    /// itself if the palette is empty.
+    pub fn nearest(&self, color: Color) -> Color {
+        match self.nearest_index(color) {
+            Some(index) => Color { a: color.a, ..self.colors[index] },
+            None => color,
+        }
+    }
+}
+
+/// Replaces the foreground and background colors of every cell of `canvas` with the closest
+/// colors of `palette`, e.g. for a limited palette "retro" look.
+pub fn quantize<G, C>(canvas: &mut C, palette: &Palette)
+where
+    G: CustomGlyph,
+    C: Canvas<G>,
+{
+    // Canvases usually have few distinct colors.
+    let mut nearest = HashMap::new();
+    let mut nearest = |color| *nearest.entry(color).or_insert_with(|| palette.nearest(color));
+    let (width, height) = canvas.size();
+    for y in 0..height {
+        for x in 0..width {
+            let mut cell = canvas.get_cell(x, y);
+            cell.foreground_color = nearest(cell.foreground_color);
+            cell.background_color = nearest(cell.background_color);
+            canvas.set_cell(x, y, cell);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::canvas::{Cell, CellBuffer, CustomGlyph, Glyph};
+use crate::{cp437, Canvas, Color};
+
+/// The version REXPaint writes at the start of its files.
+const XP_VERSION: i32 = -1;
+
+/// REXPaint draws cells with this background color as transparent.
+const TRANSPARENT_BACKGROUND: [u8; 3] = [255, 0, 255];
+
+/// The size of a cell in the file: a glyph code and two RGB colors.
+const CELL_SIZE: usize = 10;
+
+#[derive(Debug)]
+pub enum XpError {
+    Io(io::Error),
+    /// The file is not a valid REXPaint image.
+    Format(&'static str),
+    /// A cell's glyph has no CP437 code, e.g. a custom glyph. Only returned when writing.
+    UnencodableGlyph {
+        layer: usize,
+        x: usize,
+        y: usize,
+    },
+    /// The layer count or a layer size doesn't fit in the file's 32-bit fields. Only returned
+    /// when writing.
+    TooLarge,
+}
+
+impl std::fmt::Display for XpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            XpError::Io(err) => write!(f, "{}", err),
+            XpError::Format(message) => write!(f, "invalid REXPaint file: {}", message),
+            XpError::UnencodableGlyph { layer, x, y } => write!(
+                f,
+                "glyph at ({}, {}) in layer {} has no CP437 code",
+                x, y, layer,
+            ),
+            XpError::TooLarge => write!(f, "too many or too large layers for a REXPaint file"),
+        }
+    }
+}
+
+impl std::error::Error for XpError {}
+
+impl From<io::Error> for XpError {
+    fn from(err: io::Error) -> XpError {
+        XpError::Io(err)
+    }
+}
+
+/// Reads the layers of a gzipped REXPaint `.xp` image, bottom layer first.
+///
+/// Glyph codes are translated from CP437 to `Glyph::Char`. Cells with REXPaint's magenta
+/// transparent background get a background color with an alpha of 0.
+pub fn read_xp<C: CustomGlyph, R: Read>(reader: R) -> Result<Vec<CellBuffer<C>>, XpError> {
+    let mut reader = GzDecoder::new(reader);
+
+    // Files from before REXPaint 1.02 start with the layer count instead of a version.
+    let mut layer_count = read_i32(&mut reader)?;
+    if layer_count < 0 {
+        layer_count = read_i32(&mut reader)?;
+    }
+    if layer_count < 0 {
+        return Err(XpError::Format("negative layer count"));
+    }
+
+    let mut layers = Vec::new();
+    for _ in 0..layer_count {
+        let width = read_i32(&mut reader)?;
+        let height = read_i32(&mut reader)?;
+        if width < 0 || height < 0 {
+            return Err(XpError::Format("negative layer size"));
+        }
+        let (width, height) = (width as usize, height as usize);
+        let size = width
+            .checked_mul(height)
+            .and_then(|cells| cells.checked_mul(CELL_SIZE))
+            .ok_or(XpError::Format("unexpected end of file"))?;
+        // Only decompress what the layer needs, so a small file can't claim a huge layer and
+        // exhaust memory before the data runs out.
+        let mut data = Vec::new();
+        (&mut reader).take(size as u64).read_to_end(&mut data)?;
+        if data.len() < size {
+            return Err(XpError::Format("unexpected end of file"));
+        }
+        let mut data = &data[..];
+        let mut layer = CellBuffer::new(width, height, Cell::default());
+        // Cells are stored column by column.
+        for x in 0..width {
+            for y in 0..height {
+                let code = read_i32(&mut data)?;
+                let mut rgb = [0; 6];
+                read_bytes(&mut data, &mut rgb)?;
+                let c = if (0..256).contains(&code) {
+                    cp437::to_char(code as u8)
+                } else {
+                    return Err(XpError::Format("glyph code outside of CP437"));
+                };
+                let foreground_color = Color { r: rgb[0], g: rgb[1], b: rgb[2], a: 255 };
+                let mut background_color = Color { r: rgb[3], g: rgb[4], b: rgb[5], a: 255 };
+                if rgb[3..6] == TRANSPARENT_BACKGROUND {
+                    background_color.a = 0;
+                }
+                let cell = Cell {
+                    glyph: Glyph::Char(c),
+                    foreground_color,
+                    background_color,
+                    ..Cell::default()
+                };
+                layer.set_cell(x, y, cell);
+            }
+        }
+        layers.push(layer);
+    }
+    Ok(layers)
+}
+
+/// Writes layers as a gzipped REXPaint `.xp` image, bottom layer first.
+///
+/// Backgrounds with an alpha of 0 are written as REXPaint's magenta transparent background.
+/// Continuation cells are written as spaces.
+pub fn write_xp<C, W>(writer: W, layers: &[CellBuffer<C>]) -> Result<(), XpError>
+where
+    C: CustomGlyph,
+    W: Write,
+{
+    let to_i32 = |value: usize| i32::try_from(value).map_err(|_| XpError::TooLarge);
+    let mut data = Vec::new();
+    data.extend_from_slice(&XP_VERSION.to_le_bytes());
+    data.extend_from_slice(&to_i32(layers.len())?.to_le_bytes());
+    for (layer_index, layer) in layers.iter().enumerate() {
+        let (width, height) = layer.size();
+        data.extend_from_slice(&to_i32(width)?.to_le_bytes());
+        data.extend_from_slice(&to_i32(height)?.to_le_bytes());
+        for x in 0..width {
+            for y in 0..height {
+                let cell = layer.get_cell(x, y);
+                let code = match cell.glyph {
+                    Glyph::Char(c) => cp437::from_char(c),
+                    Glyph::Custom(_) => None,
+                    Glyph::Continuation => Some(b' '),
+                };
+                let code = code.ok_or(XpError::UnencodableGlyph { layer: layer_index, x, y })?;
+                data.extend_from_slice(&i32::from(code).to_le_bytes());
+                let foreground = cell.foreground_color;
+                data.extend_from_slice(&[foreground.r, foreground.g, foreground.b]);
+                let background = cell.background_color;
+                if background.a == 0 {
+                    data.extend_from_slice(&TRANSPARENT_BACKGROUND);
+                } else {
+                    data.extend_from_slice(&[background.r, background.g, background.b]);
+                }
+            }
+        }
+    }
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    encoder.write_all(&data)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+pub fn load_xp<C: CustomGlyph, P: AsRef<Path>>(path: P) -> Result<Vec<CellBuffer<C>>, XpError> {
+    read_xp(BufReader::new(File::open(path)?))
+}
+
+pub fn save_xp<C, P>(path: P, layers: &[CellBuffer<C>]) -> Result<(), XpError>
+where
+    C: CustomGlyph,
+    P: AsRef<Path>,
+{
+    write_xp(BufWriter::new(File::create(path)?), layers)
+}
+
+/// Merges layers into one, the way REXPaint displays them: cells of upper layers with a
+/// transparent background are skipped. The result has the size of the bottom layer.
+pub fn flatten<C: CustomGlyph>(layers: &[CellBuffer<C>]) -> Option<CellBuffer<C>> {
+    let (bottom, upper_layers) = layers.split_first()?;
+    let mut flattened = bottom.clone();
+    let (width, height) = flattened.size();
+    for layer in upper_layers {
+        let (layer_width, layer_height) = layer.size();
+        for y in 0..height.min(layer_height) {
+            for x in 0..width.min(layer_width) {
+                let cell = layer.get_cell(x, y);
+                if cell.background_color.a != 0 {
+                    flattened.set_cell(x, y, cell);
+                }
+            }
+        }
+    }
+    Some(flattened)
+}
+
+fn read_bytes<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), XpError> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => XpError::Format("unexpected end of file"),
+        _ => XpError::Io(err),
+    })
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> Result<i32, XpError> {
+    let mut bytes = [0; 4];
+    read_bytes(reader, &mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl CustomGlyph for Tile {}
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn header(layer_count: i32, width: i32, height: i32) -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in &[XP_VERSION, layer_count, width, height] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    fn read(data: &[u8]) -> Result<Vec<CellBuffer<Tile>>, XpError> {
+        read_xp(&gzip(data)[..])
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bottom = CellBuffer::new(3, 2, Cell::default());
+        bottom.set_cell(2, 1, Cell {
+            glyph: Glyph::Char('░'),
+            foreground_color: Color { r: 1, g: 2, b: 3, a: 255 },
+            background_color: Color { r: 4, g: 5, b: 6, a: 255 },
+            ..Cell::default()
+        });
+        let mut top = CellBuffer::new(3, 2, Cell {
+            background_color: Color { r: 255, g: 0, b: 255, a: 0 },
+            ..Cell::default()
+        });
+        top.set_cell(0, 0, Cell { glyph: Glyph::Char('@'), ..Cell::default() });
+        let layers = vec![bottom, top];
+
+        let mut data = Vec::new();
+        write_xp(&mut data, &layers).unwrap();
+        let read_layers = read_xp::<Tile, _>(&data[..]).unwrap();
+        assert_eq!(read_layers, layers);
+
+        let flattened = flatten(&read_layers).unwrap();
+        assert_eq!(flattened.get_cell(0, 0).glyph, Glyph::Char('@'));
+        assert_eq!(flattened.get_cell(2, 1), layers[0].get_cell(2, 1));
+    }
+
+    #[test]
+    fn unversioned() {
+        let mut data = Vec::new();
+        for value in &[1i32, 1, 1, b'#' as i32] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[255, 255, 255, 0, 0, 0]);
+        let layers = read(&data).unwrap();
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].get_cell(0, 0).glyph, Glyph::Char('#'));
+    }
+
+    #[test]
+    fn unencodable_glyph() {
+        let layer = CellBuffer::new(2, 1, Cell { glyph: Glyph::Custom(Tile), ..Cell::default() });
+        match write_xp(Vec::new(), &[layer]) {
+            Err(XpError::UnencodableGlyph { layer: 0, x: 0, y: 0 }) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(read_xp::<Tile, _>(&b"not a gzip file"[..]), Err(XpError::Io(_))));
+        // Too short for a gzip header.
+        assert!(matches!(read_xp::<Tile, _>(&b"gz"[..]), Err(XpError::Format(_))));
+        let cases = [
+            header(-5, 1, 1)[..8].to_vec(),
+            header(1, -1, 1),
+            header(1, 1, -1),
+            // Sizes far larger than the data.
+            header(i32::MAX, 1, 1),
+            header(1, i32::MAX, i32::MAX),
+            header(1, 2, 2),
+        ];
+        for data in &cases {
+            assert!(matches!(read(data), Err(XpError::Format(_))));
+        }
+
+        let mut bad_glyph = header(1, 1, 1);
+        bad_glyph.extend_from_slice(&256i32.to_le_bytes());
+        bad_glyph.extend_from_slice(&[0; 6]);
+        assert!(matches!(read(&bad_glyph), Err(XpError::Format(_))));
+    }
+
+    #[test]
+    fn huge_layers_are_not_decompressed_past_the_data() {
+        // Claims a 65536 by 65536 layer, but holds a single cell.
+        let mut data = header(1, 1 << 16, 1 << 16);
+        data.extend_from_slice(&[0; CELL_SIZE]);
+        assert!(matches!(read(&data), Err(XpError::Format(_))));
+    }
+
+    #[test]
+    fn too_large_to_write() {
+        // Empty, so it takes no memory, but too wide for the file's 32-bit width.
+        let layer = CellBuffer::<Tile>::new(i32::MAX as usize + 1, 0, Cell::default());
+        assert!(matches!(write_xp(Vec::new(), &[layer]), Err(XpError::TooLarge)));
+    }
+}
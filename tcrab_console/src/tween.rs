@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// How a tween's progress is mapped over its duration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Easing {
+    Linear,
+    /// Starts slow.
+    EaseIn,
+    /// Ends slow.
+    EaseOut,
+    /// Starts and ends slow.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps `t`, from 0 to 1, to the progress of the tween.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tween {
+    from: (f32, f32),
+    to: (f32, f32),
+    start: Instant,
+}
+
+impl Tween {
+    fn position(&self, now: Instant, duration: Duration, easing: Easing) -> (f32, f32) {
+        let elapsed = now.saturating_duration_since(self.start);
+        let t = if duration == Duration::from_secs(0) {
+            1.0
+        } else {
+            elapsed.as_secs_f32() / duration.as_secs_f32()
+        };
+        let progress = easing.apply(t);
+        (
+            self.from.0 + (self.to.0 - self.from.0) * progress,
+            self.from.1 + (self.to.1 - self.from.1) * progress,
+        )
+    }
+}
+
+/// Slides things such as the glyphs of moving creatures between positions over several frames.
+///
+/// Each frame, set where everything is with `set_target` and draw it where `position` says, e.g.
+/// as a sprite. Things that moved slide from where they were shown to their new position.
+/// Positions are usually in cells, but any unit works.
+#[derive(Debug, Clone)]
+pub struct Tweener<K: Eq + Hash> {
+    tweens: HashMap<K, Tween>,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl<K: Eq + Hash> Tweener<K> {
+    /// Creates a tweener whose movements take `duration`.
+    pub fn new(duration: Duration) -> Tweener<K> {
+        Tweener {
+            tweens: HashMap::new(),
+            duration,
+            easing: Easing::default(),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    pub fn easing(&self) -> Easing {
+        self.easing
+    }
+
+    pub fn set_easing(&mut self, easing: Easing) {
+        self.easing = easing;
+    }
+
+    /// Sets where `key` is at `now`. If it was somewhere else, it slides there from where it is
+    /// currently shown. New keys start at `position`.
+    pub fn set_target(&mut self, key: K, position: (f32, f32), now: Instant) {
+        let (duration, easing) = (self.duration, self.easing);
+        match self.tweens.get_mut(&key) {
+            Some(tween) if tween.to == position => {}
+            Some(tween) => {
+                tween.from = tween.position(now, duration, easing);
+                tween.to = position;
+                tween.start = now;
+            }
+            None => {
+                let tween = Tween { from: position, to: position, start: now };
+                self.tweens.insert(key, tween);
+            }
+        }
+    }
+
+    /// Moves `key` to `position` at once, stopping any movement.
+    pub fn jump(&mut self, key: K, position: (f32, f32), now: Instant) {
+        self.tweens.insert(key, Tween { from: position, to: position, start: now });
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        self.tweens.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        self.tweens.clear();
+    }
+
+    /// Where `key` is shown at `now`.
+    pub fn position(&self, key: &K, now: Instant) -> Option<(f32, f32)> {
+        let tween = self.tweens.get(key)?;
+        Some(tween.position(now, self.duration, self.easing))
+    }
+
+    /// Whether anything is still moving at `now`, so frames should keep being presented.
+    pub fn is_animating(&self, now: Instant) -> bool {
+        self.tweens.values().any(|tween| {
+            tween.from != tween.to && now.saturating_duration_since(tween.start) < self.duration
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tweener() -> Tweener<u32> {
+        let mut tweener = Tweener::new(Duration::from_secs(1));
+        tweener.set_easing(Easing::Linear);
+        tweener
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn easing_endpoints() {
+        for &easing in &[Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(-1.0), 0.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn slides_to_the_target() {
+        let start = Instant::now();
+        let mut tweener = tweener();
+        tweener.set_target(0, (0.0, 0.0), start);
+        assert_eq!(tweener.position(&0, start), Some((0.0, 0.0)));
+        assert!(!tweener.is_animating(start));
+
+        tweener.set_target(0, (4.0, 8.0), start);
+        assert_eq!(tweener.position(&0, start), Some((0.0, 0.0)));
+        assert_eq!(tweener.position(&0, start + ms(250)), Some((1.0, 2.0)));
+        assert_eq!(tweener.position(&0, start + ms(1000)), Some((4.0, 8.0)));
+        assert_eq!(tweener.position(&0, start + ms(5000)), Some((4.0, 8.0)));
+        assert!(tweener.is_animating(start + ms(999)));
+        assert!(!tweener.is_animating(start + ms(1000)));
+        assert_eq!(tweener.position(&1, start), None);
+    }
+
+    #[test]
+    fn setting_the_same_target_keeps_moving() {
+        let start = Instant::now();
+        let mut tweener = tweener();
+        tweener.set_target(0, (0.0, 0.0), start);
+        tweener.set_target(0, (4.0, 0.0), start);
+        tweener.set_target(0, (4.0, 0.0), start + ms(500));
+        assert_eq!(tweener.position(&0, start + ms(500)), Some((2.0, 0.0)));
+        assert_eq!(tweener.position(&0, start + ms(1000)), Some((4.0, 0.0)));
+    }
+
+    #[test]
+    fn retargeting_starts_from_the_shown_position() {
+        let start = Instant::now();
+        let mut tweener = tweener();
+        tweener.set_target(0, (0.0, 0.0), start);
+        tweener.set_target(0, (4.0, 8.0), start);
+        tweener.set_target(0, (2.0, 0.0), start + ms(500));
+        assert_eq!(tweener.position(&0, start + ms(500)), Some((2.0, 4.0)));
+        assert_eq!(tweener.position(&0, start + ms(1000)), Some((2.0, 2.0)));
+        assert!(tweener.is_animating(start + ms(1000)));
+        assert_eq!(tweener.position(&0, start + ms(1500)), Some((2.0, 0.0)));
+        assert!(!tweener.is_animating(start + ms(1500)));
+    }
+
+    #[test]
+    fn jump_stops_moving() {
+        let start = Instant::now();
+        let mut tweener = tweener();
+        tweener.set_target(0, (0.0, 0.0), start);
+        tweener.set_target(0, (4.0, 8.0), start);
+        tweener.jump(0, (-1.0, 3.0), start + ms(500));
+        assert_eq!(tweener.position(&0, start + ms(500)), Some((-1.0, 3.0)));
+        assert!(!tweener.is_animating(start + ms(500)));
+        tweener.jump(1, (5.0, 5.0), start);
+        assert_eq!(tweener.position(&1, start), Some((5.0, 5.0)));
+    }
+
+    #[test]
+    fn zero_duration_moves_at_once() {
+        let start = Instant::now();
+        let mut tweener = tweener();
+        tweener.set_duration(Duration::from_secs(0));
+        tweener.set_target(0, (0.0, 0.0), start);
+        tweener.set_target(0, (4.0, 8.0), start);
+        assert_eq!(tweener.position(&0, start), Some((4.0, 8.0)));
+        assert!(!tweener.is_animating(start));
+    }
+}
@@ -1,14 +1,33 @@
 #version 330
 
+// Must match `gfx::render_mode_value` and `gfx::BACKGROUND_RENDER_MODE`.
+const int RENDER_MODE_BACKGROUND = -1;
+const int RENDER_MODE_ALPHA_MASK = 0;
+const int RENDER_MODE_FULL_COLOR = 1;
+const int RENDER_MODE_TINTED = 2;
+
 in vec2 v_TexCoord;
-in vec3 v_ForegroundColor;
-in vec3 v_BackgroundColor;
+in vec4 v_Color;
+flat in float v_RenderMode;
 
 out vec4 o_Color;
 
 uniform sampler2D u_Texture;
 
 void main() {
-    float alpha = texture2D(u_Texture, v_TexCoord).a;
-    o_Color = vec4(mix(v_BackgroundColor, v_ForegroundColor, vec3(alpha)), 1.0);
+    int render_mode = int(round(v_RenderMode));
+    if (render_mode == RENDER_MODE_BACKGROUND) {
+        o_Color = v_Color;
+        return;
+    }
+
+    // Glyphs are blended over the backgrounds, which are drawn first.
+    vec4 texel = texture2D(u_Texture, v_TexCoord);
+    vec3 glyph_color = v_Color.rgb;
+    if (render_mode == RENDER_MODE_FULL_COLOR) {
+        glyph_color = texel.rgb;
+    } else if (render_mode == RENDER_MODE_TINTED) {
+        glyph_color = texel.rgb * v_Color.rgb;
+    }
+    o_Color = vec4(glyph_color, texel.a * v_Color.a);
 }
\ No newline at end of file
@@ -0,0 +1,32 @@
+#version 330
+
+in vec2 v_TexCoord;
+
+out vec4 o_Color;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_Resolution;
+// x: brightness threshold, y: intensity, z: radius in pixels.
+uniform vec4 u_Params;
+
+const int TAPS = 6;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+
+    // Blur the parts brighter than the threshold and add them back.
+    vec2 tap_offset = u_Params.z / float(TAPS) / u_Resolution;
+    vec3 glow = vec3(0.0);
+    float total_weight = 0.0;
+    for (int y = -TAPS; y <= TAPS; y++) {
+        for (int x = -TAPS; x <= TAPS; x++) {
+            vec2 offset = vec2(x, y) / float(TAPS);
+            float weight = exp(-2.0 * dot(offset, offset));
+            vec3 sample_color = texture(u_Texture, v_TexCoord + vec2(x, y) * tap_offset).rgb;
+            float brightness = max(sample_color.r, max(sample_color.g, sample_color.b));
+            glow += sample_color * weight * step(u_Params.x, brightness);
+            total_weight += weight;
+        }
+    }
+    o_Color = vec4(color.rgb + glow / total_weight * u_Params.y, color.a);
+}
\ No newline at end of file
@@ -0,0 +1,46 @@
+#version 330
+
+// Must match `post::color_blindness_value`.
+const int PROTANOPIA = 0;
+const int DEUTERANOPIA = 1;
+const int TRITANOPIA = 2;
+const int ACHROMATOPSIA = 3;
+
+in vec2 v_TexCoord;
+
+out vec4 o_Color;
+
+uniform sampler2D u_Texture;
+// x: kind of color blindness, y: severity from 0 to 1.
+uniform vec4 u_Params;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+
+    // Simulation matrices from Machado, Oliveira and Fernandes (2009), at full severity.
+    mat3 simulation;
+    int kind = int(round(u_Params.x));
+    if (kind == PROTANOPIA) {
+        simulation = mat3(
+            0.152286, 0.114503, -0.003882,
+            1.052583, 0.786281, -0.048116,
+            -0.204868, 0.099216, 1.051998);
+    } else if (kind == DEUTERANOPIA) {
+        simulation = mat3(
+            0.367322, 0.280085, -0.011820,
+            0.860646, 0.672501, 0.042940,
+            -0.227968, 0.047413, 0.968881);
+    } else if (kind == TRITANOPIA) {
+        simulation = mat3(
+            1.255528, -0.078411, 0.004733,
+            -0.076749, 0.930809, 0.691367,
+            -0.178779, 0.147602, 0.303900);
+    } else {
+        simulation = mat3(
+            0.2126, 0.2126, 0.2126,
+            0.7152, 0.7152, 0.7152,
+            0.0722, 0.0722, 0.0722);
+    }
+    vec3 simulated = clamp(simulation * color.rgb, 0.0, 1.0);
+    o_Color = vec4(mix(color.rgb, simulated, u_Params.y), color.a);
+}
\ No newline at end of file
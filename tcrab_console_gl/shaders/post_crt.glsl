@@ -0,0 +1,25 @@
+#version 330
+
+in vec2 v_TexCoord;
+
+out vec4 o_Color;
+
+uniform sampler2D u_Texture;
+// x: curvature, y: vignette strength.
+uniform vec4 u_Params;
+
+void main() {
+    // Bend the screen outwards from its center, like the glass of a CRT.
+    vec2 centered = v_TexCoord * 2.0 - 1.0;
+    centered *= 1.0 + u_Params.x * centered.yx * centered.yx;
+    vec2 tex_coord = centered * 0.5 + 0.5;
+    if (any(lessThan(tex_coord, vec2(0.0))) || any(greaterThan(tex_coord, vec2(1.0)))) {
+        o_Color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec4 color = texture(u_Texture, tex_coord);
+    vec2 edge = tex_coord * (1.0 - tex_coord);
+    float vignette = pow(clamp(edge.x * edge.y * 16.0, 0.0, 1.0), u_Params.y);
+    o_Color = vec4(color.rgb * vignette, color.a);
+}
\ No newline at end of file
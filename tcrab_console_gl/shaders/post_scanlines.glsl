@@ -0,0 +1,21 @@
+#version 330
+
+in vec2 v_TexCoord;
+
+out vec4 o_Color;
+
+uniform sampler2D u_Texture;
+uniform vec2 u_Resolution;
+// x: intensity, y: lines per cell, or 0 for one line every two pixels.
+uniform vec4 u_Params;
+uniform vec2 u_CellSize;
+
+const float PI = 3.14159265;
+
+void main() {
+    vec4 color = texture(u_Texture, v_TexCoord);
+    float period = u_Params.y > 0.0 ? u_CellSize.y / u_Params.y : 2.0;
+    float y = (1.0 - v_TexCoord.y) * u_Resolution.y;
+    float line = 0.5 + 0.5 * cos(2.0 * PI * y / period);
+    o_Color = vec4(color.rgb * (1.0 - u_Params.x * line), color.a);
+}
\ No newline at end of file
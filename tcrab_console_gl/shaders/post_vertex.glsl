@@ -0,0 +1,10 @@
+#version 330
+
+// A triangle covering the whole viewport, without any vertex attributes.
+out vec2 v_TexCoord;
+
+void main() {
+    vec2 position = vec2(float((gl_VertexID & 1) << 2), float((gl_VertexID & 2) << 1)) - 1.0;
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_TexCoord = position * 0.5 + 0.5;
+}
\ No newline at end of file
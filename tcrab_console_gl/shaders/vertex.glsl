@@ -2,18 +2,24 @@
 
 layout(location = 0) in vec2 a_Position;
 layout(location = 1) in vec2 a_TexCoord;
-layout(location = 2) in vec3 a_ForegroundColor;
-layout(location = 3) in vec3 a_BackgroundColor;
+layout(location = 2) in vec4 a_Color;
+layout(location = 3) in float a_RenderMode;
+layout(location = 4) in vec2 a_QuadCoord;
+layout(location = 5) in uvec4 a_UserData;
 
 out vec2 v_TexCoord;
-out vec3 v_ForegroundColor;
-out vec3 v_BackgroundColor;
+out vec4 v_Color;
+flat out float v_RenderMode;
+out vec2 v_QuadCoord;
+flat out uvec4 v_UserData;
 
 uniform mat4 u_Projection;
 
 void main() {
     gl_Position = u_Projection * vec4(a_Position, 0.0, 1.0);
     v_TexCoord = a_TexCoord;
-    v_ForegroundColor = a_ForegroundColor;
-    v_BackgroundColor = a_BackgroundColor;
+    v_Color = a_Color;
+    v_RenderMode = a_RenderMode;
+    v_QuadCoord = a_QuadCoord;
+    v_UserData = a_UserData;
 }
\ No newline at end of file
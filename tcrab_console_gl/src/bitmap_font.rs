@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+
+use tcrab_console::canvas::{CustomGlyph, GlyphLibrary};
+
+use crate::TextureRegion;
+use crate::texture::{GridAtlas, Image};
+
+const ATLAS_COLUMNS: u32 = 16;
+/// The largest glyph width or height accepted, in pixels. Larger values come from damaged files.
+const MAX_GLYPH_SIZE: u32 = 512;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02;
+const PSF1_MODE_HAS_SEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_START_SEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_START_SEQ: u8 = 0xfe;
+
+#[derive(Debug)]
+pub enum BitmapFontError {
+    Io(std::io::Error),
+    Bdf { line: usize, message: String },
+    Psf(&'static str),
+}
+
+impl std::fmt::Display for BitmapFontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BitmapFontError::Io(err) => write!(f, "{}", err),
+            BitmapFontError::Bdf { line, message } => write!(f, "BDF line {}: {}", line, message),
+            BitmapFontError::Psf(message) => write!(f, "PSF: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BitmapFontError {}
+
+impl From<std::io::Error> for BitmapFontError {
+    fn from(err: std::io::Error) -> BitmapFontError {
+        BitmapFontError::Io(err)
+    }
+}
+
+/// A bitmap font rendered into an atlas, with one cell-sized slot per glyph.
+///
+/// Supports X11 BDF fonts and Linux console PSF (version 1 and 2) fonts. Set pixels are opaque
+/// white in the atlas, so it can be used as a regular alpha-mask tileset.
+#[derive(Debug, Clone)]
+pub struct BitmapFont {
+    cell_width: u32,
+    cell_height: u32,
+    image: Image,
+    regions: HashMap<char, TextureRegion>,
+}
+
+impl BitmapFont {
+    pub fn load_bdf<P: AsRef<std::path::Path>>(path: P) -> Result<BitmapFont, BitmapFontError> {
+        BitmapFont::parse_bdf(&std::fs::read(path)?)
+    }
+
+    pub fn load_psf<P: AsRef<std::path::Path>>(path: P) -> Result<BitmapFont, BitmapFontError> {
+        BitmapFont::parse_psf(&std::fs::read(path)?)
+    }
+
+    /// Parses a BDF font. The cell size is the font's bounding box and glyph encodings are
+    /// assumed to be Unicode code points.
+    pub fn parse_bdf(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+        bdf::parse(data)
+    }
+
+    /// Parses a PSF1 or PSF2 font.
+    ///
+    /// Characters are mapped through the font's Unicode table if it has one. Otherwise glyph `n`
+    /// is mapped to the code point `n`.
+    pub fn parse_psf(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+        if data.starts_with(&PSF2_MAGIC) {
+            psf::parse_psf2(data)
+        } else if data.starts_with(&PSF1_MAGIC) {
+            psf::parse_psf1(data)
+        } else {
+            Err(BitmapFontError::Psf("not a PSF font"))
+        }
+    }
+
+    pub fn cell_width(&self) -> u32 {
+        self.cell_width
+    }
+
+    pub fn cell_height(&self) -> u32 {
+        self.cell_height
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+
+    pub fn char_region(&self, c: char) -> Option<TextureRegion> {
+        self.regions.get(&c).cloned()
+    }
+
+    /// Defines every character of the font in `glyph_lib`.
+    pub fn define_glyphs<C: CustomGlyph>(&self, glyph_lib: &mut GlyphLibrary<C, TextureRegion>) {
+        for (&c, &region) in &self.regions {
+            glyph_lib.define_glyph(c, region);
+        }
+    }
+
+    pub fn glyph_library<C: CustomGlyph>(&self) -> GlyphLibrary<C, TextureRegion> {
+        let mut glyph_lib = GlyphLibrary::new();
+        self.define_glyphs(&mut glyph_lib);
+        glyph_lib
+    }
+}
+
+/// Accumulates glyph bitmaps into a grid atlas.
+struct Builder {
+    cell_width: u32,
+    cell_height: u32,
+    atlas: GridAtlas,
+    regions: HashMap<char, TextureRegion>,
+}
+
+impl Builder {
+    fn new(cell_width: u32, cell_height: u32, glyph_count: u32) -> Builder {
+        let rows = glyph_count.div_ceil(ATLAS_COLUMNS);
+        Builder {
+            cell_width,
+            cell_height,
+            atlas: GridAtlas::new(cell_width, cell_height, ATLAS_COLUMNS, rows),
+            regions: HashMap::new(),
+        }
+    }
+
+    fn allocate(&mut self) -> TextureRegion {
+        self.atlas.allocate()
+    }
+
+    fn set_pixel(&mut self, region: TextureRegion, x: u32, y: u32) {
+        self.atlas.put_pixel(region, x, y, [255, 255, 255, 255]);
+    }
+
+    fn map_char(&mut self, c: char, region: TextureRegion) {
+        self.regions.insert(c, region);
+    }
+
+    fn finish(self) -> BitmapFont {
+        BitmapFont {
+            cell_width: self.cell_width,
+            cell_height: self.cell_height,
+            image: self.atlas.into_image(),
+            regions: self.regions,
+        }
+    }
+}
+
+mod bdf {
+    use super::{BitmapFont, BitmapFontError, Builder, MAX_GLYPH_SIZE};
+
+    struct Glyph {
+        encoding: Option<char>,
+        bbx: (i32, i32, i32, i32),
+        rows: Vec<Vec<u8>>,
+    }
+
+    fn error<T>(line: usize, message: &str) -> Result<T, BitmapFontError> {
+        Err(BitmapFontError::Bdf { line, message: message.into() })
+    }
+
+    fn parse_ints(line: usize, args: &str, count: usize) -> Result<Vec<i32>, BitmapFontError> {
+        let values = args
+            .split_whitespace()
+            .map(|arg| arg.parse::<i32>())
+            .collect::<Result<Vec<_>, _>>();
+        match values {
+            Ok(ref values) if values.len() >= count => Ok(values[..count].to_vec()),
+            _ => error(line, &format!("expected {} integers", count)),
+        }
+    }
+
+    /// Checks that no value of a bounding box is out of proportion with `MAX_GLYPH_SIZE`.
+    fn check_bbx(line: usize, values: &[i32]) -> Result<(), BitmapFontError> {
+        if values.iter().any(|value| value.unsigned_abs() > MAX_GLYPH_SIZE) {
+            return error(line, "bounding box too large");
+        }
+        Ok(())
+    }
+
+    fn parse_hex_row(line: usize, row: &str) -> Result<Vec<u8>, BitmapFontError> {
+        if row.len() % 2 != 0 {
+            return error(line, "bitmap row has an odd number of hex digits");
+        }
+        (0..row.len())
+            .step_by(2)
+            .map(|i| row.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<_>>>()
+            .map_or_else(|| error(line, "invalid bitmap row"), Ok)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+        let text = String::from_utf8_lossy(data);
+        let mut font_bbx = None;
+        let mut glyphs = Vec::new();
+        let mut current: Option<Glyph> = None;
+        let mut in_bitmap = false;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            let (keyword, args) = match line.find(char::is_whitespace) {
+                Some(split) => (&line[..split], line[split..].trim()),
+                None => (line, ""),
+            };
+
+            if in_bitmap {
+                if keyword == "ENDCHAR" {
+                    in_bitmap = false;
+                    glyphs.extend(current.take());
+                } else if let Some(glyph) = current.as_mut() {
+                    glyph.rows.push(parse_hex_row(line_number, line)?);
+                }
+                continue;
+            }
+
+            match keyword {
+                "FONTBOUNDINGBOX" => {
+                    let v = parse_ints(line_number, args, 4)?;
+                    if v[0] <= 0 || v[1] <= 0 {
+                        return error(line_number, "empty font bounding box");
+                    }
+                    check_bbx(line_number, &v)?;
+                    font_bbx = Some((v[0], v[1], v[2], v[3]));
+                }
+                "STARTCHAR" => {
+                    current = Some(Glyph { encoding: None, bbx: (0, 0, 0, 0), rows: Vec::new() });
+                }
+                "ENCODING" => {
+                    let v = parse_ints(line_number, args, 1)?;
+                    if let Some(glyph) = current.as_mut() {
+                        glyph.encoding = if v[0] >= 0 {
+                            std::char::from_u32(v[0] as u32)
+                        } else { None };
+                    }
+                }
+                "BBX" => {
+                    let v = parse_ints(line_number, args, 4)?;
+                    check_bbx(line_number, &v)?;
+                    if let Some(glyph) = current.as_mut() {
+                        glyph.bbx = (v[0], v[1], v[2], v[3]);
+                    }
+                }
+                "BITMAP" => {
+                    if current.is_none() {
+                        return error(line_number, "BITMAP outside of a character");
+                    }
+                    in_bitmap = true;
+                }
+                "ENDCHAR" => {
+                    glyphs.extend(current.take());
+                }
+                _ => {}
+            }
+        }
+
+        let (font_width, font_height, font_x, font_y) = match font_bbx {
+            Some(bbx) => bbx,
+            None => return error(0, "missing FONTBOUNDINGBOX"),
+        };
+        // Row of the baseline, counted from the top of the cell.
+        let baseline = font_height + font_y;
+
+        let glyphs: Vec<_> = glyphs.into_iter().filter(|glyph| glyph.encoding.is_some()).collect();
+        let mut builder = Builder::new(font_width as u32, font_height as u32, glyphs.len() as u32);
+        for glyph in glyphs {
+            let region = builder.allocate();
+            let (width, height, offset_x, offset_y) = glyph.bbx;
+            let left = offset_x - font_x;
+            let top = baseline - (offset_y + height);
+            for (row_index, row) in glyph.rows.iter().enumerate().take(height.max(0) as usize) {
+                // Bits past the end of the row are unset.
+                for column in 0..width.min(row.len() as i32 * 8) {
+                    let byte = row.get(column as usize / 8).cloned().unwrap_or(0);
+                    if byte & (0x80 >> (column % 8)) != 0 {
+                        let x = left + column;
+                        let y = top + row_index as i32;
+                        if x >= 0 && y >= 0 {
+                            builder.set_pixel(region, x as u32, y as u32);
+                        }
+                    }
+                }
+            }
+            // Filtered above, always `Some`.
+            builder.map_char(glyph.encoding.unwrap(), region);
+        }
+        Ok(builder.finish())
+    }
+}
+
+mod psf {
+    use super::*;
+
+    fn read_u32(data: &[u8], offset: usize) -> Result<u32, BitmapFontError> {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .ok_or(BitmapFontError::Psf("truncated header"))
+    }
+
+    /// Copies `glyph_count` glyph bitmaps of `bytes_per_glyph` bytes each into a new atlas.
+    ///
+    /// The header values are checked against the data before the atlas is allocated.
+    fn read_glyphs(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        glyph_count: u32,
+        bytes_per_glyph: usize,
+    ) -> Result<(Builder, Vec<TextureRegion>), BitmapFontError> {
+        if width == 0 || height == 0 {
+            return Err(BitmapFontError::Psf("empty glyph size"));
+        }
+        if width > MAX_GLYPH_SIZE || height > MAX_GLYPH_SIZE {
+            return Err(BitmapFontError::Psf("glyph size too large"));
+        }
+        let bytes_per_row = (width as usize).div_ceil(8);
+        if bytes_per_row * height as usize > bytes_per_glyph {
+            return Err(BitmapFontError::Psf("glyph size doesn't match its dimensions"));
+        }
+        match (glyph_count as usize).checked_mul(bytes_per_glyph) {
+            Some(len) if len <= data.len() => {}
+            _ => return Err(BitmapFontError::Psf("truncated glyph data")),
+        }
+        let mut builder = Builder::new(width, height, glyph_count);
+        let mut regions = Vec::with_capacity(glyph_count as usize);
+        for glyph in data.chunks(bytes_per_glyph).take(glyph_count as usize) {
+            let region = builder.allocate();
+            for y in 0..height {
+                for x in 0..width {
+                    let byte = glyph[y as usize * bytes_per_row + x as usize / 8];
+                    if byte & (0x80 >> (x % 8)) != 0 {
+                        builder.set_pixel(region, x, y);
+                    }
+                }
+            }
+            regions.push(region);
+        }
+        Ok((builder, regions))
+    }
+
+    fn map_identity(builder: &mut Builder, regions: &[TextureRegion]) {
+        for (index, &region) in regions.iter().enumerate() {
+            if let Some(c) = std::char::from_u32(index as u32) {
+                builder.map_char(c, region);
+            }
+        }
+    }
+
+    pub fn parse_psf1(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+        let (mode, char_size) = match data.get(2..4) {
+            Some(header) => (header[0], header[1]),
+            None => return Err(BitmapFontError::Psf("truncated header")),
+        };
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let glyph_data = &data[4..];
+        let (mut builder, regions) =
+            read_glyphs(glyph_data, 8, char_size as u32, glyph_count, char_size as usize)?;
+
+        if mode & (PSF1_MODE_HAS_TABLE | PSF1_MODE_HAS_SEQ) == 0 {
+            map_identity(&mut builder, &regions);
+            return Ok(builder.finish());
+        }
+
+        let table = &glyph_data[glyph_count as usize * char_size as usize..];
+        let mut entries = table
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+        for &region in &regions {
+            let mut in_sequence = false;
+            loop {
+                match entries.next() {
+                    None | Some(PSF1_SEPARATOR) => break,
+                    Some(PSF1_START_SEQ) => in_sequence = true,
+                    // Multi-character sequences can't be expressed as a single `Glyph::Char`.
+                    Some(_) if in_sequence => {}
+                    Some(code) => {
+                        if let Some(c) = std::char::from_u32(code as u32) {
+                            builder.map_char(c, region);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(builder.finish())
+    }
+
+    pub fn parse_psf2(data: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+        let header_size = read_u32(data, 8)? as usize;
+        let flags = read_u32(data, 12)?;
+        let glyph_count = read_u32(data, 16)?;
+        let bytes_per_glyph = read_u32(data, 20)? as usize;
+        let height = read_u32(data, 24)?;
+        let width = read_u32(data, 28)?;
+        let glyph_data = data.get(header_size..).ok_or(BitmapFontError::Psf("truncated header"))?;
+        let (mut builder, regions) =
+            read_glyphs(glyph_data, width, height, glyph_count, bytes_per_glyph)?;
+
+        if flags & PSF2_HAS_UNICODE_TABLE == 0 {
+            map_identity(&mut builder, &regions);
+            return Ok(builder.finish());
+        }
+
+        let mut table = &glyph_data[glyph_count as usize * bytes_per_glyph..];
+        for &region in &regions {
+            let end = table.iter().position(|&b| b == PSF2_SEPARATOR).unwrap_or(table.len());
+            let entry = &table[..end];
+            table = table.get(end + 1..).unwrap_or(&[]);
+            // Single characters come first, multi-character sequences follow after a
+            // `PSF2_START_SEQ` and can't be expressed as a single `Glyph::Char`.
+            let singles = entry.split(|&b| b == PSF2_START_SEQ).next().unwrap_or(&[]);
+            for c in String::from_utf8_lossy(singles).chars() {
+                if c != std::char::REPLACEMENT_CHARACTER {
+                    builder.map_char(c, region);
+                }
+            }
+        }
+        Ok(builder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_set(font: &BitmapFont, c: char, x: u32, y: u32) -> bool {
+        let region = font.char_region(c).unwrap();
+        let image = font.image();
+        let index = ((region.y + y) as usize * image.width as usize + (region.x + x) as usize) * 4;
+        image.data[index + 3] == 255
+    }
+
+    /// A PSF1 font with 256 glyphs of 8 by `char_size` pixels, where glyph `n` has its first row
+    /// set to `n`.
+    fn psf1(mode: u8, char_size: u8) -> Vec<u8> {
+        let mut data = vec![PSF1_MAGIC[0], PSF1_MAGIC[1], mode, char_size];
+        for n in 0..256 {
+            let mut glyph = vec![0; char_size as usize];
+            if let Some(row) = glyph.first_mut() {
+                *row = n as u8;
+            }
+            data.extend(glyph);
+        }
+        data
+    }
+
+    fn psf2_header(glyph_count: u32, bytes_per_glyph: u32, height: u32, width: u32) -> Vec<u8> {
+        let mut data = PSF2_MAGIC.to_vec();
+        for value in &[0, 32, PSF2_HAS_UNICODE_TABLE, glyph_count, bytes_per_glyph, height, width] {
+            data.extend_from_slice(&u32::to_le_bytes(*value));
+        }
+        data
+    }
+
+    #[test]
+    fn psf1_identity() {
+        let font = BitmapFont::parse_psf(&psf1(0, 4)).unwrap();
+        assert_eq!((font.cell_width(), font.cell_height()), (8, 4));
+        // 'A' is 0b01000001.
+        let row: Vec<_> = (0..8).map(|x| is_set(&font, 'A', x, 0)).collect();
+        assert_eq!(row, [false, true, false, false, false, false, false, true]);
+        assert!(!is_set(&font, 'A', 1, 1));
+        assert!(font.char_region('\u{ff}').is_some());
+        assert!(font.char_region('\u{100}').is_none());
+    }
+
+    #[test]
+    fn psf1_unicode_table() {
+        let mut data = psf1(PSF1_MODE_HAS_TABLE, 2);
+        // Glyph 0 is 'é' and 'e', glyph 1 only a sequence, the rest nothing.
+        for entry in &[0xe9, 0x65, PSF1_SEPARATOR, PSF1_START_SEQ, 0x65, 0x301, PSF1_SEPARATOR] {
+            data.extend_from_slice(&u16::to_le_bytes(*entry));
+        }
+        let font = BitmapFont::parse_psf(&data).unwrap();
+        assert_eq!(font.char_region('é'), font.char_region('e'));
+        assert!(font.char_region('é').is_some());
+        assert!(font.char_region('\u{301}').is_none());
+        assert!(font.char_region('A').is_none());
+    }
+
+    #[test]
+    fn psf2_unicode_table() {
+        let mut data = psf2_header(2, 4, 2, 10);
+        data.extend_from_slice(&[0xff, 0xc0, 0, 0, 0, 0, 0, 0x80]);
+        data.extend_from_slice("ab".as_bytes());
+        data.push(PSF2_SEPARATOR);
+        data.extend_from_slice("é".as_bytes());
+        data.push(PSF2_START_SEQ);
+        data.extend_from_slice("e\u{301}".as_bytes());
+        data.push(PSF2_SEPARATOR);
+
+        let font = BitmapFont::parse_psf(&data).unwrap();
+        assert_eq!((font.cell_width(), font.cell_height()), (10, 2));
+        assert_eq!(font.char_region('a'), font.char_region('b'));
+        assert!((0..10).all(|x| is_set(&font, 'a', x, 0)));
+        assert!(is_set(&font, 'é', 8, 1));
+        assert!(!is_set(&font, 'é', 0, 1));
+        assert!(font.char_region('e').is_none());
+    }
+
+    #[test]
+    fn psf_malformed() {
+        assert!(BitmapFont::parse_psf(b"").is_err());
+        assert!(BitmapFont::parse_psf(&[PSF1_MAGIC[0], PSF1_MAGIC[1], 0]).is_err());
+        assert!(BitmapFont::parse_psf(&psf1(0, 0)).is_err());
+        let mut truncated = psf1(0, 8);
+        truncated.pop();
+        assert!(BitmapFont::parse_psf(&truncated).is_err());
+
+        let cases = [
+            // Empty, huge and mismatched glyph sizes.
+            psf2_header(1, 1, 0, 8),
+            psf2_header(1, 1, 1, 0),
+            psf2_header(1, u32::MAX, u32::MAX, u32::MAX),
+            psf2_header(1, 1, 2, 8),
+            // Glyph data far larger than the file.
+            psf2_header(u32::MAX, u32::MAX, 8, 8),
+            psf2_header(u32::MAX, 1, 1, 8),
+        ];
+        for data in &cases {
+            assert!(BitmapFont::parse_psf(data).is_err());
+        }
+        assert!(BitmapFont::parse_psf(&psf2_header(1, 1, 1, 8)[..20]).is_err());
+    }
+
+    const BDF: &str = "\
+STARTFONT 2.1
+FONT test
+SIZE 8 75 75
+FONTBOUNDINGBOX 4 6 0 -2
+CHARS 2
+STARTCHAR A
+ENCODING 65
+BBX 4 4 0 0
+BITMAP
+60
+90
+F0
+90
+ENDCHAR
+STARTCHAR g
+ENCODING 103
+BBX 3 2 1 -2
+BITMAP
+20
+C0
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn bdf() {
+        let font = BitmapFont::parse_bdf(BDF.as_bytes()).unwrap();
+        assert_eq!((font.cell_width(), font.cell_height()), (4, 6));
+        // 'A' sits on the baseline, two rows above the bottom.
+        let row: Vec<_> = (0..4).map(|x| is_set(&font, 'A', x, 2)).collect();
+        assert_eq!(row, [true, true, true, true]);
+        assert!(is_set(&font, 'A', 1, 0));
+        assert!(!is_set(&font, 'A', 0, 0));
+        assert!(!is_set(&font, 'A', 0, 4));
+        // 'g' is offset one column right and descends below the baseline.
+        assert!(is_set(&font, 'g', 3, 4));
+        assert!(is_set(&font, 'g', 1, 5));
+        assert!(!is_set(&font, 'g', 0, 5));
+        assert_eq!(font.regions.len(), 2);
+    }
+
+    #[test]
+    fn bdf_malformed() {
+        let cases = [
+            ("FONTBOUNDINGBOX 4 6 0 -2", "FONTBOUNDINGBOX 0 6 0 -2"),
+            ("FONTBOUNDINGBOX 4 6 0 -2", "FONTBOUNDINGBOX 4 6"),
+            ("FONTBOUNDINGBOX 4 6 0 -2", "FONTBOUNDINGBOX 2147483647 2147483647 0 0"),
+            ("FONTBOUNDINGBOX 4 6 0 -2", "COMMENT no bounding box"),
+            ("BBX 4 4 0 0", "BBX 2147483647 4 -2147483648 0"),
+            ("F0", "F"),
+            ("F0", "G0"),
+        ];
+        for &(from, to) in &cases {
+            let data = BDF.replacen(from, to, 1);
+            assert!(BitmapFont::parse_bdf(data.as_bytes()).is_err(), "{}", to);
+        }
+        // Bitmap rows wider than the glyph are ignored.
+        let data = BDF.replacen("F0", "FFFFFFFF", 1);
+        assert!(BitmapFont::parse_bdf(data.as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use rusttype::{Font, Scale, point};
+
+use tcrab_console::Canvas;
+use tcrab_console::canvas::{CustomGlyph, Glyph, GlyphLibrary};
+
+use crate::TextureRegion;
+use crate::texture::{GridAtlas, Image};
+
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_INITIAL_ROWS: u32 = 16;
+
+/// Rasterizes a TrueType/OpenType font into a glyph atlas, one cell-sized slot per character.
+///
+/// Characters are rasterized on demand and the atlas grows as needed. Coverage is antialiased
+/// into the alpha channel, so the resulting image can be used as a regular alpha-mask tileset.
+pub struct FontAtlas {
+    font: Font<'static>,
+    scale: Scale,
+    origin_y: f32,
+    cell_width: u32,
+    cell_height: u32,
+    atlas: GridAtlas,
+    regions: HashMap<char, TextureRegion>,
+}
+
+impl FontAtlas {
+    /// Parses a font from memory. The font is scaled to fit inside a `cell_width` by
+    /// `cell_height` cell.
+    pub fn from_bytes(
+        bytes: Vec<u8>,
+        cell_width: u32,
+        cell_height: u32,
+    ) -> Result<FontAtlas, rusttype::Error> {
+        let font = Font::from_bytes(bytes)?;
+
+        // Fit the font vertically, then shrink it if its widest common glyph would overflow the
+        // cell horizontally.
+        let mut scale = Scale::uniform(cell_height as f32);
+        let advance = font.glyph('M').scaled(scale).h_metrics().advance_width;
+        if advance > cell_width as f32 {
+            scale = Scale::uniform(cell_height as f32 * cell_width as f32 / advance);
+        }
+        let v_metrics = font.v_metrics(scale);
+        let font_height = v_metrics.ascent - v_metrics.descent;
+        let origin_y = ((cell_height as f32 - font_height) / 2.0 + v_metrics.ascent).round();
+
+        Ok(FontAtlas {
+            font,
+            scale,
+            origin_y,
+            cell_width,
+            cell_height,
+            atlas: GridAtlas::new(cell_width, cell_height, ATLAS_COLUMNS, ATLAS_INITIAL_ROWS),
+            regions: HashMap::new(),
+        })
+    }
+
+    /// Reads and parses a font file.
+    pub fn load<P: AsRef<std::path::Path>>(
+        path: P,
+        cell_width: u32,
+        cell_height: u32,
+    ) -> std::io::Result<FontAtlas> {
+        let bytes = std::fs::read(path)?;
+        Ok(FontAtlas::from_bytes(bytes, cell_width, cell_height)?)
+    }
+
+    pub fn cell_width(&self) -> u32 {
+        self.cell_width
+    }
+
+    pub fn cell_height(&self) -> u32 {
+        self.cell_height
+    }
+
+    /// The atlas image containing every character rasterized so far.
+    pub fn image(&self) -> &Image {
+        self.atlas.image()
+    }
+
+    pub fn into_image(self) -> Image {
+        self.atlas.into_image()
+    }
+
+    /// Returns the region of `c` in the atlas, rasterizing it first if needed.
+    pub fn char_region(&mut self, c: char) -> TextureRegion {
+        if let Some(region) = self.regions.get(&c) {
+            return *region;
+        }
+        let region = self.atlas.allocate();
+        let glyph = self.font.glyph(c).scaled(self.scale);
+        let offset_x = ((self.cell_width as f32 - glyph.h_metrics().advance_width) / 2.0).round();
+        let glyph = glyph.positioned(point(offset_x, self.origin_y));
+        if let Some(bounds) = glyph.pixel_bounding_box() {
+            let atlas = &mut self.atlas;
+            glyph.draw(|x, y, coverage| {
+                let x = bounds.min.x + x as i32;
+                let y = bounds.min.y + y as i32;
+                if x >= 0 && y >= 0 {
+                    let alpha = (coverage * 255.0).round() as u8;
+                    atlas.put_pixel(region, x as u32, y as u32, [255, 255, 255, alpha]);
+                }
+            });
+        }
+        self.regions.insert(c, region);
+        region
+    }
+
+    /// Rasterizes each character and defines it in `glyph_lib`.
+    pub fn define_chars<C, I>(&mut self, glyph_lib: &mut GlyphLibrary<C, TextureRegion>, chars: I)
+    where
+        C: CustomGlyph,
+        I: IntoIterator<Item = char>,
+    {
+        for c in chars {
+            let region = self.char_region(c);
+            glyph_lib.define_glyph(c, region);
+        }
+    }
+
+    /// Defines every character used by `canvas` that `glyph_lib` doesn't know about yet.
+    ///
+    /// Returns `true` if any new character was added, in which case the atlas image has changed
+    /// and needs to be uploaded again.
+    pub fn define_canvas_chars<C, V>(
+        &mut self,
+        glyph_lib: &mut GlyphLibrary<C, TextureRegion>,
+        canvas: &V,
+    ) -> bool
+    where
+        C: CustomGlyph,
+        V: Canvas<C>,
+    {
+        let mut changed = false;
+        let (width, height) = canvas.size();
+        for y in 0..height {
+            for x in 0..width {
+                if let Glyph::Char(c) = canvas.get_cell(x, y).glyph {
+                    if !glyph_lib.contains_glyph(c) {
+                        let region = self.char_region(c);
+                        glyph_lib.define_glyph(c, region);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
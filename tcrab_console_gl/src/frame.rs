@@ -0,0 +1,202 @@
+use gl::types::{GLsizei, GLsizeiptr, GLuint};
+
+use tcrab_console::canvas::{CustomGlyph, GlyphLibrary};
+use tcrab_console::Canvas;
+
+use crate::{gfx, Background, ColorSpace, Console, Layer, LayerId, Sprite, TextureRegion};
+
+/// A frame being composited from several canvases, started with `Console::begin_frame`.
+///
+/// Canvases are drawn in the order of the calls to `draw`, so later canvases such as menus and
+/// message logs are drawn over earlier ones such as the map.
+pub struct Frame<'a> {
+    console: &'a mut Console,
+    width: u32,
+    height: u32,
+}
+
+impl<'a> Frame<'a> {
+    pub(crate) fn new(console: &'a mut Console, width: u32, height: u32) -> Frame<'a> {
+        console.windowed_context.window().set_inner_size(
+            glutin::dpi::LogicalSize::new(width as f64, height as f64));
+        console.windowed_context.window().show();
+        console.frame_size = (width, height);
+        unsafe {
+            // Frames are drawn offscreen and copied to the window by `finish`, so they can be read
+            // back after being shown.
+            let output = if console.output.bind(width, height, console.color_space) {
+                console.output.framebuffer
+            } else {
+                0
+            };
+            console.post.begin(width, height, console.color_space, output);
+            // The clear color is converted like any other write to an sRGB framebuffer.
+            let [r, g, b, a] = gfx::vertex_color(console.clear_color, console.color_space);
+            glcheck!(gl::ClearColor(r, g, b, a));
+            glcheck!(gl::Clear(gl::COLOR_BUFFER_BIT));
+            glcheck!(gl::Enable(gl::BLEND));
+            // Destination alpha accumulates coverage, like `rasterize` does.
+            glcheck!(gl::BlendFuncSeparate(
+                gl::SRC_ALPHA,
+                gl::ONE_MINUS_SRC_ALPHA,
+                gl::ONE,
+                gl::ONE_MINUS_SRC_ALPHA,
+            ));
+            // An sRGB framebuffer blends in linear light, converting on read and write.
+            match console.color_space {
+                ColorSpace::Srgb => glcheck!(gl::Disable(gl::FRAMEBUFFER_SRGB)),
+                ColorSpace::Linear => glcheck!(gl::Enable(gl::FRAMEBUFFER_SRGB)),
+            }
+
+            glcheck!(gl::Viewport(0, 0, width as GLsizei, height as GLsizei));
+            let program = &console.cell_program;
+            glcheck!(gl::UseProgram(program.id));
+            glcheck!(gl::BindVertexArray(console.vertex_array_object));
+            glcheck!(gl::ActiveTexture(gl::TEXTURE0));
+            glcheck!(gl::Uniform1i(program.texture_location, 0));
+            let time = console.start.elapsed().as_secs_f32();
+            glcheck!(gl::Uniform1f(program.time_location, time));
+        }
+        let frame = Frame { console, width, height };
+        if let Some(background) = &frame.console.background {
+            let (w, h) = (width as f32, height as f32);
+            let tex_rect = match background {
+                Background::Stretched(_) => [0.0, 0.0, 1.0, 1.0],
+                Background::Tiled(image) => {
+                    [0.0, 0.0, w / image.width as f32, h / image.height as f32]
+                }
+            };
+            let geom = gfx::gen_image_geometry([0.0, 0.0, w, h], tex_rect);
+            frame.draw_geometry(&geom, frame.console.background_texture, 0, 0);
+        }
+        frame
+    }
+
+    /// Draws `canvas` with the texture and cell size of `layer`, with its top left corner at
+    /// `(x, y)` pixels from the top left of the window.
+    pub fn draw<G, C>(
+        &mut self,
+        layer: LayerId,
+        canvas: &C,
+        glyph_lib: &GlyphLibrary<G, TextureRegion>,
+        x: i32,
+        y: i32,
+    ) where
+        G: CustomGlyph,
+        C: Canvas<G>,
+    {
+        let layer = &self.console.layers[layer.0];
+        let (cell_width, cell_height) = layer.cell_size();
+        let texture = layer.texture();
+        let geom = gfx::gen_canvas_geometry(
+            canvas,
+            cell_width,
+            cell_height,
+            texture.width,
+            texture.height,
+            glyph_lib,
+            layer.glyph_styles(),
+            self.console.color_space,
+            self.console.blink_visible(),
+        );
+        self.draw_layer_geometry(layer, &geom, x, y);
+    }
+
+    /// Draws `sprites` in order with the texture and cell size of `layer`, over a canvas whose top
+    /// left corner is at `(x, y)` pixels from the top left of the window.
+    pub fn draw_sprites<G: CustomGlyph>(
+        &mut self,
+        layer: LayerId,
+        sprites: &[Sprite<G>],
+        glyph_lib: &GlyphLibrary<G, TextureRegion>,
+        x: i32,
+        y: i32,
+    ) {
+        let layer = &self.console.layers[layer.0];
+        let (cell_width, cell_height) = layer.cell_size();
+        let texture = layer.texture();
+        let geom = gfx::gen_sprite_geometry(
+            sprites,
+            cell_width,
+            cell_height,
+            texture.width,
+            texture.height,
+            glyph_lib,
+            layer.glyph_styles(),
+            self.console.color_space,
+        );
+        self.draw_layer_geometry(layer, &geom, x, y);
+    }
+
+    /// Draws geometry generated for `layer`, with its texture and cell size.
+    fn draw_layer_geometry(&self, layer: &Layer, geom: &gfx::CanvasGeometry, x: i32, y: i32) {
+        let (cell_width, cell_height) = layer.cell_size();
+        unsafe {
+            glcheck!(gl::Uniform2f(
+                self.console.cell_program.cell_size_location,
+                cell_width as f32,
+                cell_height as f32,
+            ));
+        }
+        self.draw_geometry(geom, layer.texture, x, y);
+    }
+
+    /// Draws geometry laid out from the top left with `texture`, offset by `(x, y)` pixels.
+    fn draw_geometry(&self, geom: &gfx::CanvasGeometry, texture: GLuint, x: i32, y: i32) {
+        unsafe {
+            // Geometry is laid out from the top left, like the canvas. Offsetting the projection
+            // moves the canvas to its position in the window.
+            let projection_matrix = gfx::ortho_matrix(
+                -x as f32, self.width as f32 - x as f32,
+                self.height as f32 - y as f32, -y as f32,
+                -0.1, 1.0,
+            );
+            glcheck!(gl::UniformMatrix4fv(
+                self.console.cell_program.projection_location,
+                1,
+                gl::FALSE,
+                projection_matrix.as_ptr(),
+            ));
+
+            glcheck!(gl::BindBuffer(gl::ARRAY_BUFFER, self.console.vertex_buffer_object));
+            glcheck!(gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (geom.vertices.len() * std::mem::size_of::<gfx::Vertex>()) as GLsizeiptr,
+                geom.vertices.as_ptr() as *const _,
+                gl::DYNAMIC_DRAW,
+            ));
+            glcheck!(gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.console.index_buffer_object));
+            glcheck!(gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                (geom.indices.len() * std::mem::size_of::<gfx::Index>()) as GLsizeiptr,
+                geom.indices.as_ptr() as *const _,
+                gl::DYNAMIC_DRAW,
+            ));
+
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, texture));
+
+            glcheck!(gl::DrawElements(
+                gl::TRIANGLES,
+                geom.indices.len() as GLsizei,
+                gfx::GL_INDEX_TYPE,
+                std::ptr::null(),
+            ));
+        }
+    }
+
+    /// Runs the post-processing effects and shows the frame, scaled to the window's framebuffer on
+    /// HiDPI displays.
+    pub fn finish(self) {
+        let console = self.console;
+        let cell_size = console.layer(LayerId::MAIN).cell_size();
+        unsafe {
+            let time = console.start.elapsed().as_secs_f32();
+            console.post.finish(cell_size, time);
+            if console.output.is_complete() {
+                let (width, height) = console.physical_size();
+                console.post.present(console.output.texture, width, height);
+            }
+        }
+        console.windowed_context.swap_buffers().unwrap();
+    }
+}
@@ -1,16 +1,27 @@
+use std::ffi::CString;
+
 use gl::types::{GLenum, GLuint, GLchar, GLint, GLsizei};
 
+use tcrab_console::canvas::{Attributes, Cell, Glyph, UserData};
+use tcrab_console::Color;
+
+use crate::{ColorSpace, GlyphStyle, GlyphStyles, Image, RenderMode, ShaderError, Sprite};
 use crate::TextureRegion;
 
-pub type Index = u16;
-pub const GL_INDEX_TYPE: GLenum = gl::UNSIGNED_SHORT;
+pub type Index = u32;
+pub const GL_INDEX_TYPE: GLenum = gl::UNSIGNED_INT;
 
+#[repr(C)]
 #[derive(Debug, Clone)]
 pub struct Vertex {
     pub position: [f32; 2],
     pub tex_coord: [f32; 2],
-    pub foreground_color: [f32; 3],
-    pub background_color: [f32; 3],
+    pub color: [f32; 4],
+    pub render_mode: f32,
+    /// From `(0, 0)` at the top left of the quad to `(1, 1)` at the bottom right.
+    pub quad_coord: [f32; 2],
+    /// The cell's `UserData`: its flags, then its params.
+    pub user_data: [u8; 4],
 }
 
 impl Vertex {
@@ -38,34 +49,98 @@ impl Vertex {
             vertex_size,
             std::mem::size_of::<[f32; 2]>() as *const _,
         ));
-        // Foreground color
+        // Color
         glcheck!(gl::EnableVertexAttribArray(2));
         glcheck!(gl::VertexAttribPointer(
             2,
-            3,
+            4,
             gl::FLOAT,
             gl::FALSE,
             vertex_size,
             std::mem::size_of::<[f32; 4]>() as *const _,
         ));
-        // Background color
+        // Render mode
         glcheck!(gl::EnableVertexAttribArray(3));
         glcheck!(gl::VertexAttribPointer(
             3,
-            3,
+            1,
             gl::FLOAT,
             gl::FALSE,
             vertex_size,
-            std::mem::size_of::<[f32; 7]>() as *const _,
+            std::mem::size_of::<[f32; 8]>() as *const _,
+        ));
+        // Quad coord
+        glcheck!(gl::EnableVertexAttribArray(4));
+        glcheck!(gl::VertexAttribPointer(
+            4,
+            2,
+            gl::FLOAT,
+            gl::FALSE,
+            vertex_size,
+            std::mem::size_of::<[f32; 9]>() as *const _,
+        ));
+        // User data, as integers
+        glcheck!(gl::EnableVertexAttribArray(5));
+        glcheck!(gl::VertexAttribIPointer(
+            5,
+            4,
+            gl::UNSIGNED_BYTE,
+            vertex_size,
+            std::mem::size_of::<[f32; 11]>() as *const _,
         ));
     }
 }
 
+/// Render mode value for solid cell backgrounds, which don't sample the texture.
+const BACKGROUND_RENDER_MODE: f32 = -1.0;
+
 pub struct CanvasGeometry {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<Index>,
 }
 
+impl CanvasGeometry {
+    fn with_capacity(quads: usize) -> CanvasGeometry {
+        CanvasGeometry {
+            vertices: Vec::with_capacity(quads * 4),
+            indices: Vec::with_capacity(quads * 6),
+        }
+    }
+
+    /// Adds a quad. `rect` is `[x, y, width, height]` in pixels from the top left of the window
+    /// and `tex_rect` is the same in normalized texture coordinates.
+    fn push_quad(
+        &mut self,
+        rect: [f32; 4],
+        tex_rect: [f32; 4],
+        color: [f32; 4],
+        render_mode: f32,
+        user_data: [u8; 4],
+    ) {
+        let [x, y, w, h] = rect;
+        let [tex_x, tex_y, tex_w, tex_h] = tex_rect;
+        let i = self.vertices.len() as Index;
+        self.indices.extend_from_slice(&[i, i + 1, i + 2, i, i + 3, i + 1]);
+        let vertex = |corner_x: f32, corner_y: f32| Vertex {
+            position: [x + w * corner_x, y + h * corner_y],
+            tex_coord: [tex_x + tex_w * corner_x, tex_y + tex_h * corner_y],
+            color,
+            render_mode,
+            quad_coord: [corner_x, corner_y],
+            user_data,
+        };
+        self.vertices.extend_from_slice(&[
+            vertex(0.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 1.0),
+            vertex(1.0, 0.0),
+        ]);
+    }
+}
+
+/// Generates the quads drawing `canvas`. Glyphs and lines of blinking cells are left out unless
+/// `blink_visible` is set.
+#[allow(clippy::too_many_arguments)]
 pub fn gen_canvas_geometry<G, C>(
     canvas: &C,
     cell_width: u32,
@@ -73,61 +148,232 @@ pub fn gen_canvas_geometry<G, C>(
     texture_width: u32,
     texture_height: u32,
     glyph_lib: &tcrab_console::canvas::GlyphLibrary<G, TextureRegion>,
+    glyph_styles: &GlyphStyles,
+    color_space: ColorSpace,
+    blink_visible: bool,
 ) -> CanvasGeometry
 where
     G: tcrab_console::canvas::CustomGlyph,
     C: tcrab_console::Canvas<G>,
 {
-    let cell_width = cell_width as f32;
-    let cell_height = cell_height as f32;
     let texture_width = texture_width as f32;
     let texture_height = texture_height as f32;
     let (width_cells, height_cells) = canvas.size();
-    let mut vertices = Vec::with_capacity(width_cells * height_cells * 4);
-    let mut indices = Vec::with_capacity(width_cells * height_cells * 6);
+    let mut geom = CanvasGeometry::with_capacity(width_cells * height_cells * 2);
+
+    // All backgrounds go first, so glyphs covering several cells are drawn over the backgrounds
+    // of their neighbors.
+    for cell_y in 0..height_cells {
+        for cell_x in 0..width_cells {
+            let cell = canvas.get_cell(cell_x, cell_y);
+            let (_, background_color) = cell_colors(&cell);
+            geom.push_quad(
+                [
+                    (cell_x as u32 * cell_width) as f32,
+                    (cell_y as u32 * cell_height) as f32,
+                    cell_width as f32,
+                    cell_height as f32,
+                ],
+                [0.0; 4],
+                vertex_color(background_color, color_space),
+                BACKGROUND_RENDER_MODE,
+                user_data_value(cell.user_data),
+            );
+        }
+    }
+
+    for cell_y in 0..height_cells {
+        for cell_x in 0..width_cells {
+            let cell = canvas.get_cell(cell_x, cell_y);
+            if let Glyph::Continuation = cell.glyph {
+                continue;
+            }
+            if !blink_visible && cell.attributes.contains(Attributes::BLINK) {
+                continue;
+            }
+            let region = *glyph_lib.get_glyph_def(cell.glyph);
+            let style = glyph_styles.get(region);
+            let (rect, tex_rect) = glyph_quad(
+                region,
+                style,
+                (cell_x as u32 * cell_width) as f32,
+                (cell_y as u32 * cell_height) as f32,
+                cell_width,
+                cell_height,
+                texture_width,
+                texture_height,
+            );
+            let (foreground_color, _) = cell_colors(&cell);
+            geom.push_quad(
+                rect,
+                tex_rect,
+                vertex_color(foreground_color, color_space),
+                render_mode_value(style.render_mode),
+                user_data_value(cell.user_data),
+            );
+        }
+    }
+
+    // Lines go over glyphs, including the continuation cells of a glyph so they are unbroken.
     for cell_y in 0..height_cells {
-        let y = (height_cells - cell_y - 1) as f32 * cell_height;
         for cell_x in 0..width_cells {
-            let x = cell_x as f32 * cell_width;
             let cell = canvas.get_cell(cell_x, cell_y);
-            let tex_region = glyph_lib.get_glyph_def(cell.glyph);
-            let foreground_color = cell.foreground_color.to_rgb_f32();
-            let background_color = cell.background_color.to_rgb_f32();
-            let i = vertices.len() as u16;
-            let tex_x = tex_region.x as f32 / texture_width;
-            let tex_y = tex_region.y as f32 / texture_height;
-            let tex_w = tex_region.width as f32 / texture_width;
-            let tex_h = tex_region.height as f32 / texture_height;
-            indices.extend_from_slice(&[i, i + 1, i + 2, i, i + 3, i + 1]);
-            vertices.extend_from_slice(&[
-                Vertex {
-                    position: [x, y + cell_height],
-                    tex_coord: [tex_x, tex_y],
-                    foreground_color,
-                    background_color,
-                },
-                Vertex {
-                    position: [x + cell_width, y],
-                    tex_coord: [tex_x + tex_w, tex_y + tex_h],
-                    foreground_color,
-                    background_color,
-                },
-                Vertex {
-                    position: [x, y],
-                    tex_coord: [tex_x, tex_y + tex_h],
-                    foreground_color,
-                    background_color,
-                },
-                Vertex {
-                    position: [x + cell_width, y + cell_height],
-                    tex_coord: [tex_x + tex_w, tex_y],
-                    foreground_color,
-                    background_color,
-                },
-            ]);
+            if !blink_visible && cell.attributes.contains(Attributes::BLINK) {
+                continue;
+            }
+            let (foreground_color, _) = cell_colors(&cell);
+            for (line_y, line_height) in attribute_lines(cell.attributes, cell_height) {
+                geom.push_quad(
+                    [
+                        (cell_x as u32 * cell_width) as f32,
+                        (cell_y as u32 * cell_height + line_y) as f32,
+                        cell_width as f32,
+                        line_height as f32,
+                    ],
+                    [0.0; 4],
+                    vertex_color(foreground_color, color_space),
+                    BACKGROUND_RENDER_MODE,
+                    user_data_value(cell.user_data),
+                );
+            }
+        }
+    }
+    geom
+}
+
+/// Generates the quads drawing `sprites`, in order.
+#[allow(clippy::too_many_arguments)]
+pub fn gen_sprite_geometry<G>(
+    sprites: &[Sprite<G>],
+    cell_width: u32,
+    cell_height: u32,
+    texture_width: u32,
+    texture_height: u32,
+    glyph_lib: &tcrab_console::canvas::GlyphLibrary<G, TextureRegion>,
+    glyph_styles: &GlyphStyles,
+    color_space: ColorSpace,
+) -> CanvasGeometry
+where
+    G: tcrab_console::canvas::CustomGlyph,
+{
+    let mut geom = CanvasGeometry::with_capacity(sprites.len());
+    for sprite in sprites {
+        if let Glyph::Continuation = sprite.glyph {
+            continue;
         }
+        let region = *glyph_lib.get_glyph_def(sprite.glyph);
+        let style = glyph_styles.get(region);
+        let (rect, tex_rect) = glyph_quad(
+            region,
+            style,
+            sprite.x * cell_width as f32,
+            sprite.y * cell_height as f32,
+            cell_width,
+            cell_height,
+            texture_width as f32,
+            texture_height as f32,
+        );
+        geom.push_quad(
+            rect,
+            tex_rect,
+            vertex_color(sprite.color, color_space),
+            render_mode_value(style.render_mode),
+            user_data_value(sprite.user_data),
+        );
+    }
+    geom
+}
+
+/// The rect of a glyph whose top left cell is at `(x, y)` pixels, and the rect of its region in a
+/// texture of `texture_width` by `texture_height` pixels. See `CanvasGeometry::push_quad`.
+#[allow(clippy::too_many_arguments)]
+fn glyph_quad(
+    tex_region: TextureRegion,
+    style: GlyphStyle,
+    x: f32,
+    y: f32,
+    cell_width: u32,
+    cell_height: u32,
+    texture_width: f32,
+    texture_height: f32,
+) -> ([f32; 4], [f32; 4]) {
+    let rect = match style.placement {
+        Some(placement) => [
+            x + placement.x as f32,
+            y + placement.y as f32,
+            placement.width as f32,
+            placement.height as f32,
+        ],
+        None => [
+            x,
+            y,
+            (style.span.0 * cell_width) as f32,
+            (style.span.1 * cell_height) as f32,
+        ],
+    };
+    let tex_rect = [
+        tex_region.x as f32 / texture_width,
+        tex_region.y as f32 / texture_height,
+        tex_region.width as f32 / texture_width,
+        tex_region.height as f32 / texture_height,
+    ];
+    (rect, tex_rect)
+}
+
+/// The foreground and background colors a cell is drawn with, swapped by `Attributes::REVERSE`.
+pub fn cell_colors<G: tcrab_console::canvas::CustomGlyph>(cell: &Cell<G>) -> (Color, Color) {
+    if cell.attributes.contains(Attributes::REVERSE) {
+        (cell.background_color, cell.foreground_color)
+    } else {
+        (cell.foreground_color, cell.background_color)
+    }
+}
+
+/// The underline and strikethrough of a cell, as the first row and the number of rows they cover
+/// within a cell `cell_height` pixels tall.
+pub fn attribute_lines(attributes: Attributes, cell_height: u32) -> Vec<(u32, u32)> {
+    let thickness = (cell_height / 12).max(1);
+    let mut lines = Vec::new();
+    if attributes.contains(Attributes::UNDERLINE) {
+        lines.push((cell_height.saturating_sub(thickness), thickness));
+    }
+    if attributes.contains(Attributes::STRIKETHROUGH) {
+        lines.push(((cell_height / 2).saturating_sub(thickness / 2), thickness));
+    }
+    lines
+}
+
+/// A quad drawing `tex_rect` of a texture in its own colors over `rect`, e.g. a background image.
+pub fn gen_image_geometry(rect: [f32; 4], tex_rect: [f32; 4]) -> CanvasGeometry {
+    let mut geom = CanvasGeometry::with_capacity(1);
+    geom.push_quad(rect, tex_rect, [1.0; 4], render_mode_value(RenderMode::FullColor), [0; 4]);
+    geom
+}
+
+/// The value of the color attribute. In linear color space, the shader works in linear light.
+pub fn vertex_color(color: Color, color_space: ColorSpace) -> [f32; 4] {
+    let [r, g, b, a] = color.to_rgba_f32();
+    match color_space {
+        ColorSpace::Srgb => [r, g, b, a],
+        ColorSpace::Linear => {
+            let [r, g, b] = color.to_linear_rgb_f32();
+            [r, g, b, a]
+        }
+    }
+}
+
+fn user_data_value(user_data: UserData) -> [u8; 4] {
+    let [a, b, c] = user_data.params;
+    [user_data.flags, a, b, c]
+}
+
+/// The value of the render mode attribute, matching the constants in `fragment.glsl`.
+fn render_mode_value(render_mode: RenderMode) -> f32 {
+    match render_mode {
+        RenderMode::AlphaMask => 0.0,
+        RenderMode::FullColor => 1.0,
+        RenderMode::Tinted => 2.0,
     }
-    CanvasGeometry { vertices, indices }
 }
 
 pub fn ortho_matrix(
@@ -150,28 +396,221 @@ pub fn ortho_matrix(
     ]
 }
 
-pub unsafe fn link_program(vert_src: &[u8], frag_src: &[u8]) -> GLuint {
+/// Replaces the contents and size of the currently bound texture. In linear color space, the
+/// texture is sRGB encoded, so the shader samples linear colors.
+pub unsafe fn upload_texture(image: &Image, color_space: ColorSpace) {
+    glcheck!(gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        texture_internal_format(color_space) as GLint,
+        image.width as GLsizei,
+        image.height as GLsizei,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        image.data.as_ptr() as *const _,
+    ));
+}
+
+/// The format of textures holding colors. In linear color space, they are sRGB encoded, so they
+/// are sampled and blended in linear light.
+pub fn texture_internal_format(color_space: ColorSpace) -> GLenum {
+    match color_space {
+        ColorSpace::Srgb => gl::RGBA8,
+        ColorSpace::Linear => gl::SRGB8_ALPHA8,
+    }
+}
+
+/// Replaces `region` of the currently bound texture with the same region of `image`.
+pub unsafe fn upload_texture_region(image: &Image, region: TextureRegion) {
+    glcheck!(gl::PixelStorei(gl::UNPACK_ROW_LENGTH, image.width as GLint));
+    glcheck!(gl::PixelStorei(gl::UNPACK_SKIP_PIXELS, region.x as GLint));
+    glcheck!(gl::PixelStorei(gl::UNPACK_SKIP_ROWS, region.y as GLint));
+    glcheck!(gl::TexSubImage2D(
+        gl::TEXTURE_2D,
+        0,
+        region.x as GLint,
+        region.y as GLint,
+        region.width as GLsizei,
+        region.height as GLsizei,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        image.data.as_ptr() as *const _,
+    ));
+    glcheck!(gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0));
+    glcheck!(gl::PixelStorei(gl::UNPACK_SKIP_PIXELS, 0));
+    glcheck!(gl::PixelStorei(gl::UNPACK_SKIP_ROWS, 0));
+}
+
+/// A texture with a framebuffer drawing into it.
+pub struct RenderTarget {
+    pub framebuffer: GLuint,
+    pub texture: GLuint,
+    /// The size and color space the texture was allocated with, and whether the framebuffer is
+    /// complete with it.
+    format: Option<((u32, u32, ColorSpace), bool)>,
+}
+
+impl RenderTarget {
+    /// Creates a target whose texture is sampled with `filter`. Its texture is allocated by
+    /// `bind`.
+    pub unsafe fn new(filter: GLenum) -> RenderTarget {
+        let mut framebuffer = 0;
+        glcheck!(gl::GenFramebuffers(1, &mut framebuffer));
+        let mut texture = 0;
+        glcheck!(gl::GenTextures(1, &mut texture));
+        glcheck!(gl::BindTexture(gl::TEXTURE_2D, texture));
+        glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as GLint));
+        glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as GLint));
+        glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint));
+        glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint));
+        RenderTarget { framebuffer, texture, format: None }
+    }
+
+    /// Binds the framebuffer, first reallocating the texture if its size or color space differ.
+    ///
+    /// Returns `false` if the driver can't draw into the framebuffer, which is logged once per
+    /// format. Nothing is bound then.
+    pub unsafe fn bind(&mut self, width: u32, height: u32, color_space: ColorSpace) -> bool {
+        let format = (width, height, color_space);
+        if self.format.map(|(allocated, _)| allocated) != Some(format) {
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.texture));
+            glcheck!(gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                texture_internal_format(color_space) as GLint,
+                width as GLsizei,
+                height as GLsizei,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            ));
+            glcheck!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer));
+            glcheck!(gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                self.texture,
+                0,
+            ));
+            let status = glcheck!(gl::CheckFramebufferStatus(gl::FRAMEBUFFER));
+            let complete = status == gl::FRAMEBUFFER_COMPLETE;
+            if !complete {
+                log::error!(
+                    "Incomplete framebuffer for a {}x{} texture: status {:#x}",
+                    width,
+                    height,
+                    status,
+                );
+            }
+            self.format = Some((format, complete));
+        }
+        let complete = self.is_complete();
+        if complete {
+            glcheck!(gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer));
+        }
+        complete
+    }
+
+    /// Whether the framebuffer could be drawn into when last bound.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.format, Some((_, true)))
+    }
+
+    pub unsafe fn delete(&self) {
+        glcheck!(gl::DeleteFramebuffers(1, &self.framebuffer));
+        glcheck!(gl::DeleteTextures(1, &self.texture));
+    }
+}
+
+/// The program drawing canvases, with the locations of its uniforms.
+pub struct CellProgram {
+    pub id: GLuint,
+    pub projection_location: GLint,
+    pub texture_location: GLint,
+    pub cell_size_location: GLint,
+    pub time_location: GLint,
+}
+
+impl CellProgram {
+    pub unsafe fn new(vert_src: &[u8], frag_src: &[u8]) -> Result<CellProgram, ShaderError> {
+        let id = link_program(vert_src, frag_src)?;
+        Ok(CellProgram {
+            id,
+            projection_location: uniform_location(id, "u_Projection"),
+            texture_location: uniform_location(id, "u_Texture"),
+            cell_size_location: uniform_location(id, "u_CellSize"),
+            time_location: uniform_location(id, "u_Time"),
+        })
+    }
+}
+
+/// The location of a uniform, or -1 if the program doesn't use it. Setting a uniform at -1 does
+/// nothing.
+pub unsafe fn uniform_location(program: GLuint, name: &str) -> GLint {
+    let name = CString::new(name).unwrap(); // Should never fail
+    glcheck!(gl::GetUniformLocation(program, name.as_ptr()))
+}
+
+/// Compiles and links a program, returning the info log of the stage that failed.
+pub unsafe fn link_program(vert_src: &[u8], frag_src: &[u8]) -> Result<GLuint, ShaderError> {
+    let vertex_shader = compile_shader(gl::VERTEX_SHADER, vert_src)
+        .map_err(ShaderError::Vertex)?;
+    let fragment_shader = match compile_shader(gl::FRAGMENT_SHADER, frag_src) {
+        Ok(shader) => shader,
+        Err(log) => {
+            glcheck!(gl::DeleteShader(vertex_shader));
+            return Err(ShaderError::Fragment(log));
+        }
+    };
     let shader_program = glcheck!(gl::CreateProgram());
-    let vertex_shader = compile_shader(gl::VERTEX_SHADER, vert_src);
-    let fragment_shader = compile_shader(gl::FRAGMENT_SHADER, frag_src);
     glcheck!(gl::AttachShader(shader_program, vertex_shader));
     glcheck!(gl::AttachShader(shader_program, fragment_shader));
-    // Linking should theoretically fail only if the embedded shaders are incorrect.
     glcheck!(gl::LinkProgram(shader_program));
     glcheck!(gl::DetachShader(shader_program, vertex_shader));
     glcheck!(gl::DetachShader(shader_program, fragment_shader));
     glcheck!(gl::DeleteShader(vertex_shader));
     glcheck!(gl::DeleteShader(fragment_shader));
-    shader_program
+
+    let mut status = 0;
+    glcheck!(gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut status));
+    if status == gl::FALSE as GLint {
+        let log = info_log(shader_program, gl::GetProgramiv, gl::GetProgramInfoLog);
+        glcheck!(gl::DeleteProgram(shader_program));
+        return Err(ShaderError::Link(log));
+    }
+    Ok(shader_program)
 }
 
-// Since the only shaders we will compile are embedded in the library, we will ignore compile error
-// checking. Make sure the included shaders are correct :)
-unsafe fn compile_shader(type_: GLenum, src: &[u8]) -> GLuint {
+unsafe fn compile_shader(type_: GLenum, src: &[u8]) -> Result<GLuint, String> {
     let shader = glcheck!(gl::CreateShader(type_));
     let src_ptr = src.as_ptr() as *const GLchar;
     let src_len = src.len() as GLint;
     glcheck!(gl::ShaderSource(shader, 1, &src_ptr, &src_len));
     glcheck!(gl::CompileShader(shader));
-    shader
-}
\ No newline at end of file
+
+    let mut status = 0;
+    glcheck!(gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status));
+    if status == gl::FALSE as GLint {
+        let log = info_log(shader, gl::GetShaderiv, gl::GetShaderInfoLog);
+        glcheck!(gl::DeleteShader(shader));
+        return Err(log);
+    }
+    Ok(shader)
+}
+
+/// Reads the info log of a shader or program, with the matching pair of GL functions.
+unsafe fn info_log(
+    object: GLuint,
+    get_iv: unsafe fn(GLuint, GLenum, *mut GLint),
+    get_info_log: unsafe fn(GLuint, GLsizei, *mut GLsizei, *mut GLchar),
+) -> String {
+    let mut len = 0;
+    glcheck!(get_iv(object, gl::INFO_LOG_LENGTH, &mut len));
+    let mut log = vec![0u8; len.max(1) as usize];
+    let mut written = 0;
+    glcheck!(get_info_log(object, log.len() as GLsizei, &mut written, log.as_mut_ptr() as *mut _));
+    log.truncate(written.max(0) as usize);
+    String::from_utf8_lossy(&log).trim_end().to_owned()
+}
@@ -0,0 +1,125 @@
+use gl::types::GLuint;
+
+use crate::gfx;
+use crate::texture::{Image, ImageError, ShelfPacker};
+use crate::{ColorSpace, GlyphStyle, GlyphStyles, TextureRegion};
+
+/// Identifies a layer of a `Console`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerId(pub(crate) usize);
+
+impl LayerId {
+    /// The layer created from the console's `Settings`, which is used by `present`.
+    pub const MAIN: LayerId = LayerId(0);
+}
+
+/// A texture and cell size used to draw canvases.
+///
+/// Each layer has its own glyph atlas, so canvases using different fonts, e.g. a square map font
+/// and a narrow text font, can be drawn in the same frame.
+pub struct Layer {
+    pub(crate) texture: GLuint,
+    atlas: Image,
+    atlas_packer: ShelfPacker,
+    glyph_styles: GlyphStyles,
+    cell_width: u32,
+    cell_height: u32,
+    color_space: ColorSpace,
+}
+
+impl Layer {
+    /// Creates the layer's OpenGL texture. The console's context must be current.
+    pub(crate) unsafe fn new(
+        texture_image: Image,
+        cell_width: u32,
+        cell_height: u32,
+        color_space: ColorSpace,
+    ) -> Layer {
+        let mut texture = 0;
+        glcheck!(gl::GenTextures(1, &mut texture));
+        glcheck!(gl::BindTexture(gl::TEXTURE_2D, texture));
+        glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
+        glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
+        gfx::upload_texture(&texture_image, color_space);
+        Layer {
+            texture,
+            atlas_packer: ShelfPacker::new(texture_image.height),
+            atlas: texture_image,
+            glyph_styles: GlyphStyles::new(),
+            cell_width,
+            cell_height,
+            color_space,
+        }
+    }
+
+    /// The texture currently used for drawing glyphs, including glyphs added at runtime.
+    pub fn texture(&self) -> &Image {
+        &self.atlas
+    }
+
+    /// Replaces the whole texture, e.g. to switch fonts. Regions returned by `add_glyph_image`
+    /// are no longer valid afterwards.
+    pub fn set_texture(&mut self, texture: Image) {
+        self.atlas_packer = ShelfPacker::new(texture.height);
+        self.atlas = texture;
+        unsafe {
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.texture));
+            gfx::upload_texture(&self.atlas, self.color_space);
+        }
+    }
+
+    /// How the glyphs of the texture are drawn, e.g. full-color tiles or glyphs spanning several
+    /// cells.
+    pub fn glyph_styles(&self) -> &GlyphStyles {
+        &self.glyph_styles
+    }
+
+    pub fn set_glyph_style(&mut self, region: TextureRegion, style: GlyphStyle) {
+        self.glyph_styles.set(region, style);
+    }
+
+    pub fn cell_size(&self) -> (u32, u32) {
+        (self.cell_width, self.cell_height)
+    }
+
+    pub fn set_cell_size(&mut self, cell_width: u32, cell_height: u32) {
+        self.cell_width = cell_width;
+        self.cell_height = cell_height;
+    }
+
+    /// Adds a new glyph bitmap to the texture, growing it if needed, and returns its region.
+    pub fn add_glyph_image(&mut self, image: &Image) -> TextureRegion {
+        let (old_width, old_height) = (self.atlas.width, self.atlas.height);
+        let region = self.atlas_packer.pack(&mut self.atlas, image.width, image.height);
+        self.atlas.blit(image, region.x, region.y).expect("the packer grows the atlas to fit");
+        unsafe {
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.texture));
+            if (old_width, old_height) == (self.atlas.width, self.atlas.height) {
+                gfx::upload_texture_region(&self.atlas, region);
+            } else {
+                gfx::upload_texture(&self.atlas, self.color_space);
+            }
+        }
+        region
+    }
+
+    /// Overwrites the bitmap of an existing glyph. `image` must be the same size as `region`.
+    pub fn replace_glyph_image(
+        &mut self,
+        region: TextureRegion,
+        image: &Image,
+    ) -> Result<(), ImageError> {
+        if (image.width, image.height) != (region.width, region.height) {
+            return Err(ImageError::SizeMismatch {
+                expected: (region.width, region.height),
+                actual: (image.width, image.height),
+            });
+        }
+        self.atlas.blit(image, region.x, region.y)?;
+        unsafe {
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.texture));
+            gfx::upload_texture_region(&self.atlas, region);
+        }
+        Ok(())
+    }
+}
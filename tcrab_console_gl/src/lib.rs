@@ -21,22 +21,66 @@ macro_rules! glcheck {
     ($gl_expr:expr) => { $gl_expr };
 }
 
+mod bitmap_font;
 mod event;
+#[cfg(feature = "rusttype")]
+mod font;
+mod frame;
 mod gfx;
+mod layer;
+mod post;
+mod raster;
+#[cfg(any(feature = "gif", feature = "png"))]
+mod recorder;
+mod software;
+mod sprite;
+mod texture;
 
-use std::ffi::CString;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use gl::types::{GLuint, GLsizei, GLint, GLsizeiptr};
+use gl::types::{GLuint, GLsizei};
 
 use tcrab_console::canvas::GlyphLibrary;
+use tcrab_console::Color;
+
+use self::post::PostProcessor;
+
+pub use self::bitmap_font::{BitmapFont, BitmapFontError};
+pub use self::frame::Frame;
+pub use self::layer::{Layer, LayerId};
+pub use self::post::{ColorBlindness, PostEffect};
+pub use self::raster::{rasterize, rasterize_into};
+#[cfg(any(feature = "gif", feature = "png"))]
+pub use self::recorder::{
+    Capture, FrameSource, RecordError, Recorder, RecorderOptions, Recording, RecordingFormat,
+};
+pub use self::software::SoftwareConsole;
+pub use self::sprite::Sprite;
+#[cfg(feature = "image")]
+pub use self::texture::AlphaSource;
+pub use self::texture::{Image, ImageError};
+#[cfg(feature = "rusttype")]
+pub use self::font::FontAtlas;
 
 const VERTEX_SHADER_SRC: &[u8] = include_bytes!("../shaders/vertex.glsl");
-const FRAGMENT_SHADER_SRC: &[u8] = include_bytes!("../shaders/fragment.glsl");
+
+/// How long cells with `Attributes::BLINK` are shown, then hidden. Blinking only shows while frames
+/// keep being presented.
+pub const BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The fragment shader drawing canvases, a starting point for custom cell shaders. See
+/// `Console::set_cell_shader`.
+pub const DEFAULT_CELL_SHADER: &str = include_str!("../shaders/fragment.glsl");
 
 #[derive(Debug)]
 pub enum CreationError {
     Glutin(glutin::CreationError),
     Context(glutin::ContextError),
+    /// The built-in shaders failed to compile, which means the driver doesn't support them.
+    Shader(ShaderError),
+    /// The texture data in `Settings` doesn't match its size.
+    Texture(ImageError),
 }
 
 impl std::fmt::Display for CreationError {
@@ -44,12 +88,46 @@ impl std::fmt::Display for CreationError {
         match self {
             CreationError::Glutin(err) => write!(f, "{}", err),
             CreationError::Context(err) => write!(f, "{}", err),
+            CreationError::Shader(err) => write!(f, "{}", err),
+            CreationError::Texture(err) => write!(f, "{}", err),
         }
     }
 }
 
 impl std::error::Error for CreationError {}
 
+impl From<ShaderError> for CreationError {
+    fn from(err: ShaderError) -> CreationError {
+        CreationError::Shader(err)
+    }
+}
+
+impl From<ImageError> for CreationError {
+    fn from(err: ImageError) -> CreationError {
+        CreationError::Texture(err)
+    }
+}
+
+/// A shader failed to build. Each variant holds the info log of the driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderError {
+    Vertex(String),
+    Fragment(String),
+    Link(String),
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::Vertex(log) => write!(f, "vertex shader failed to compile:\n{}", log),
+            ShaderError::Fragment(log) => write!(f, "fragment shader failed to compile:\n{}", log),
+            ShaderError::Link(log) => write!(f, "shader program failed to link:\n{}", log),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TextureRegion {
     pub x: u32,
@@ -58,30 +136,175 @@ pub struct TextureRegion {
     pub height: u32,
 }
 
+/// How the texture of a glyph is combined with the colors of its cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RenderMode {
+    /// The texture's alpha mixes the foreground color over the background color. This is the usual
+    /// mode for font glyphs.
+    #[default]
+    AlphaMask,
+    /// The texture is drawn in its own colors over the background color. Only the alpha of the
+    /// foreground color is used.
+    FullColor,
+    /// Like `FullColor`, but the texture's colors are multiplied by the foreground color.
+    Tinted,
+}
+
+/// The color space in which colors are blended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    /// Blend the sRGB encoded values directly, like most 2D software. Cheap, but antialiased
+    /// edges come out too dark.
+    #[default]
+    Srgb,
+    /// Blend in linear light, through an sRGB framebuffer and sRGB textures, so antialiased
+    /// glyphs keep their weight.
+    Linear,
+}
+
+/// An image drawn behind every frame, over the clear color.
+#[derive(Debug, Clone)]
+pub enum Background {
+    /// Stretched over the whole window.
+    Stretched(Image),
+    /// Repeated from the top left of the window, e.g. a pattern.
+    Tiled(Image),
+}
+
+impl Background {
+    pub fn image(&self) -> &Image {
+        match self {
+            Background::Stretched(image) | Background::Tiled(image) => image,
+        }
+    }
+}
+
+/// A rectangle in pixels, relative to the top left of the cells covered by a glyph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How a glyph is drawn, beyond where to find it in the texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphStyle {
+    pub render_mode: RenderMode,
+    /// The number of cells covered by the glyph, extending right and down from the cell it is set
+    /// in. The other covered cells should hold `Glyph::Continuation`.
+    pub span: (u32, u32),
+    /// Where the glyph is drawn within the cells it covers. `None` stretches it over all of them.
+    pub placement: Option<Placement>,
+}
+
+impl GlyphStyle {
+    pub fn with_render_mode(mut self, render_mode: RenderMode) -> GlyphStyle {
+        self.render_mode = render_mode;
+        self
+    }
+
+    pub fn with_span(mut self, width: u32, height: u32) -> GlyphStyle {
+        self.span = (width, height);
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Placement) -> GlyphStyle {
+        self.placement = Some(placement);
+        self
+    }
+}
+
+impl Default for GlyphStyle {
+    fn default() -> GlyphStyle {
+        GlyphStyle {
+            render_mode: RenderMode::default(),
+            span: (1, 1),
+            placement: None,
+        }
+    }
+}
+
+/// The styles of the glyphs in a texture, keyed by their region. Glyphs whose region has no style
+/// are drawn with `GlyphStyle::default()`, as a one cell alpha mask.
+#[derive(Debug, Clone, Default)]
+pub struct GlyphStyles {
+    styles: HashMap<TextureRegion, GlyphStyle>,
+}
+
+impl GlyphStyles {
+    pub fn new() -> GlyphStyles {
+        GlyphStyles::default()
+    }
+
+    pub fn set(&mut self, region: TextureRegion, style: GlyphStyle) {
+        self.styles.insert(region, style);
+    }
+
+    pub fn remove(&mut self, region: TextureRegion) {
+        self.styles.remove(&region);
+    }
+
+    pub fn get(&self, region: TextureRegion) -> GlyphStyle {
+        self.styles.get(&region).copied().unwrap_or_default()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Settings {
     pub title: String,
     pub texture_width: u32,
     pub texture_height: u32,
+    /// The tileset of the main layer as RGBA pixels, row by row from the top left.
     pub texture_data: Vec<u8>,
     pub cell_width: u32,
     pub cell_height: u32,
+    /// Where glyphs are blended over backgrounds, and backgrounds with alpha over what is below
+    /// them.
+    pub color_space: ColorSpace,
+    /// The color of the window where no canvas is drawn, e.g. letterboxing around the cell grid.
+    pub clear_color: Color,
+    pub background: Option<Background>,
+}
+
+impl Settings {
+    /// Creates settings with defaults for everything but the window title and the main layer,
+    /// e.g. with a tileset decoded by `Image::load`.
+    pub fn new(title: &str, texture: Image, cell_width: u32, cell_height: u32) -> Settings {
+        Settings {
+            title: title.to_owned(),
+            texture_width: texture.width,
+            texture_height: texture.height,
+            texture_data: texture.data,
+            cell_width,
+            cell_height,
+            color_space: ColorSpace::default(),
+            clear_color: Color::BLACK,
+            background: None,
+        }
+    }
 }
 
 pub struct Console {
     events_loop: glutin::EventsLoop,
     windowed_context: glutin::WindowedContext<glutin::PossiblyCurrent>,
-    shader_program: GLuint,
-    projection_uniform_location: GLint,
-    texture_uniform_location: GLint,
+    cell_program: gfx::CellProgram,
     vertex_array_object: GLuint,
     vertex_buffer_object: GLuint,
     index_buffer_object: GLuint,
-    texture: GLuint,
-    texture_width: u32,
-    texture_height: u32,
-    cell_width: u32,
-    cell_height: u32,
+    layers: Vec<Layer>,
+    frame_size: (u32, u32),
+    color_space: ColorSpace,
+    clear_color: Color,
+    background: Option<Background>,
+    background_texture: GLuint,
+    post: PostProcessor,
+    /// The frame being drawn, copied to the window once it is finished. Screenshots are read from
+    /// it, since the window's contents are undefined after being shown.
+    output: gfx::RenderTarget,
+    /// The origin of `u_Time` in shaders.
+    start: Instant,
 }
 
 impl Console {
@@ -92,6 +315,7 @@ impl Console {
             .with_resizable(false)
             .with_title(settings.title);
         let windowed_context = glutin::ContextBuilder::new()
+            .with_srgb(settings.color_space == ColorSpace::Linear)
             .build_windowed(window_builder, &events_loop)
             .map_err(CreationError::Glutin)?;
         let windowed_context = unsafe {
@@ -101,19 +325,10 @@ impl Console {
         };
         gl::load_with(|symbol| windowed_context.get_proc_address(symbol) as *const _);
 
-        let shader_program = unsafe {
-            gfx::link_program(VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
-        };
-
-        let projection_uniform_location = unsafe {
-            let name = CString::new("u_Projection").unwrap(); // Should never fail
-            glcheck!(gl::GetUniformLocation(shader_program, name.as_ptr()))
-        };
-
-        let texture_uniform_location = unsafe {
-            let name = CString::new("u_Texture").unwrap(); // Should never fail
-            glcheck!(gl::GetUniformLocation(shader_program, name.as_ptr()))
+        let cell_program = unsafe {
+            gfx::CellProgram::new(VERTEX_SHADER_SRC, DEFAULT_CELL_SHADER.as_bytes())?
         };
+        let post = unsafe { PostProcessor::new()? };
 
         let vertex_array_object = unsafe {
             let mut id = 0;
@@ -131,41 +346,231 @@ impl Console {
             gfx::Vertex::setup_vertex_array_object(vertex_array_object, vertex_buffer_object);
         }
 
-        let texture = unsafe {
-            let mut id = 0;
-            glcheck!(gl::GenTextures(1, &mut id));
-            glcheck!(gl::BindTexture(gl::TEXTURE_2D, id));
+        let background_texture = unsafe {
+            let mut texture = 0;
+            glcheck!(gl::GenTextures(1, &mut texture));
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, texture));
             glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32));
             glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32));
-            glcheck!(gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA as GLint,
-                settings.texture_width as GLsizei,
-                settings.texture_height as GLsizei,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                settings.texture_data.as_ptr() as *const _,
-            ));
-            id
+            glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32));
+            glcheck!(gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32));
+            texture
+        };
+
+        let texture =
+            Image::new(settings.texture_width, settings.texture_height, settings.texture_data)?;
+        let main_layer = unsafe {
+            Layer::new(
+                texture,
+                settings.cell_width,
+                settings.cell_height,
+                settings.color_space,
+            )
         };
 
-        Ok(Console {
+        let mut console = Console {
             events_loop,
             windowed_context,
-            shader_program,
-            projection_uniform_location,
-            texture_uniform_location,
+            cell_program,
             vertex_array_object,
             vertex_buffer_object,
             index_buffer_object,
-            texture,
-            texture_width: settings.texture_width,
-            texture_height: settings.texture_height,
-            cell_width: settings.cell_width,
-            cell_height: settings.cell_height,
-        })
+            layers: vec![main_layer],
+            frame_size: (0, 0),
+            color_space: settings.color_space,
+            clear_color: settings.clear_color,
+            background: None,
+            background_texture,
+            post,
+            output: unsafe { gfx::RenderTarget::new(gl::NEAREST) },
+            start: Instant::now(),
+        };
+        console.set_background(settings.background);
+        Ok(console)
+    }
+
+    /// Adds a layer with its own texture and cell size, e.g. for a text font drawn alongside the
+    /// main map font.
+    pub fn add_layer(&mut self, texture: Image, cell_width: u32, cell_height: u32) -> LayerId {
+        let layer = unsafe { Layer::new(texture, cell_width, cell_height, self.color_space) };
+        self.layers.push(layer);
+        LayerId(self.layers.len() - 1)
+    }
+
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        &self.layers[id.0]
+    }
+
+    pub fn layer_mut(&mut self, id: LayerId) -> &mut Layer {
+        &mut self.layers[id.0]
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    /// Sets the color of the window where no canvas is drawn, from the next frame on.
+    pub fn set_clear_color(&mut self, clear_color: Color) {
+        self.clear_color = clear_color;
+    }
+
+    pub fn background(&self) -> Option<&Background> {
+        self.background.as_ref()
+    }
+
+    /// Sets the image drawn behind every frame, from the next frame on.
+    pub fn set_background(&mut self, background: Option<Background>) {
+        if let Some(background) = &background {
+            unsafe {
+                glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.background_texture));
+                gfx::upload_texture(background.image(), self.color_space);
+            }
+        }
+        self.background = background;
+    }
+
+    /// Whether blinking cells are shown, alternating every `BLINK_INTERVAL`.
+    pub(crate) fn blink_visible(&self) -> bool {
+        let phase = self.start.elapsed().as_millis() / BLINK_INTERVAL.as_millis();
+        phase % 2 == 0
+    }
+
+    /// Replaces the fragment shader drawing canvases, or restores `DEFAULT_CELL_SHADER` with
+    /// `None`. The shader runs for cell backgrounds, glyphs and the background image, and can
+    /// declare these inputs and uniforms:
+    ///
+    /// ```glsl
+    /// #version 330
+    ///
+    /// in vec2 v_TexCoord;
+    /// in vec4 v_Color;             // The background color, or the foreground color of a glyph.
+    /// flat in float v_RenderMode;  // -1 for backgrounds, otherwise the glyph's render mode.
+    /// in vec2 v_QuadCoord;         // From (0, 0) at the top left of the quad to (1, 1).
+    /// flat in uvec4 v_UserData;    // The cell's `UserData`: its flags, then its params.
+    /// out vec4 o_Color;
+    ///
+    /// uniform sampler2D u_Texture; // The texture of the layer.
+    /// uniform vec2 u_CellSize;     // The cell size of the layer in pixels.
+    /// uniform float u_Time;        // Seconds since the console was created.
+    /// ```
+    ///
+    /// If the shader fails to compile, the current one is kept.
+    pub fn set_cell_shader(&mut self, source: Option<&str>) -> Result<(), ShaderError> {
+        let source = source.unwrap_or(DEFAULT_CELL_SHADER);
+        let cell_program = unsafe { gfx::CellProgram::new(VERTEX_SHADER_SRC, source.as_bytes())? };
+        unsafe {
+            glcheck!(gl::DeleteProgram(self.cell_program.id));
+        }
+        self.cell_program = cell_program;
+        Ok(())
+    }
+
+    pub fn post_effects(&self) -> &[PostEffect] {
+        self.post.effects()
+    }
+
+    /// Sets the effects run over each frame before it is shown, in order. Shaders are compiled
+    /// once and reused, so effects can be set again every frame to animate their parameters. If a
+    /// shader fails to compile, the previous effects are kept.
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) -> Result<(), ShaderError> {
+        unsafe { self.post.set_effects(effects) }
+    }
+
+    /// Starts a frame of `width` by `height` pixels, resizing the window to match. Canvases drawn
+    /// in the frame are shown once it is finished.
+    pub fn begin_frame(&mut self, width: u32, height: u32) -> Frame<'_> {
+        Frame::new(self, width, height)
+    }
+
+    /// The size of the window's framebuffer in pixels, larger than the frame on HiDPI displays.
+    pub(crate) fn physical_size(&self) -> (u32, u32) {
+        let window = self.windowed_context.window();
+        let (width, height) = self.frame_size;
+        let size = window
+            .get_inner_size()
+            .unwrap_or_else(|| glutin::dpi::LogicalSize::new(width as f64, height as f64))
+            .to_physical(window.get_hidpi_factor());
+        (size.width.round() as u32, size.height.round() as u32)
+    }
+
+    /// Reads back the last presented frame, at the size it was drawn at rather than the size of
+    /// the window on HiDPI displays. The frame is black if it couldn't be drawn offscreen, which
+    /// is logged as an error.
+    pub fn screenshot(&self) -> Image {
+        let (width, height) = self.frame_size;
+        let row_len = width as usize * 4;
+        let mut data = vec![0; row_len * height as usize];
+        if self.output.is_complete() {
+            unsafe {
+                glcheck!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.output.framebuffer));
+                glcheck!(gl::ReadBuffer(gl::COLOR_ATTACHMENT0));
+                // Read sRGB encoded colors as they are. The next frame enables it again.
+                glcheck!(gl::Disable(gl::FRAMEBUFFER_SRGB));
+                glcheck!(gl::PixelStorei(gl::PACK_ALIGNMENT, 1));
+                glcheck!(gl::ReadPixels(
+                    0,
+                    0,
+                    width as GLsizei,
+                    height as GLsizei,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    data.as_mut_ptr() as *mut _,
+                ));
+                glcheck!(gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0));
+            }
+        }
+        // OpenGL returns rows from the bottom up. The window is opaque, regardless of what ended
+        // up in the framebuffer's alpha channel.
+        let mut flipped = Vec::with_capacity(data.len());
+        for y in (0..height as usize).rev() {
+            flipped.extend_from_slice(&data[y * row_len..(y + 1) * row_len]);
+        }
+        for pixel in flipped.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+        Image { width, height, data: flipped }
+    }
+
+    /// The texture of the main layer. See `Layer::texture`.
+    pub fn texture(&self) -> &Image {
+        self.layer(LayerId::MAIN).texture()
+    }
+
+    /// Replaces the texture of the main layer. See `Layer::set_texture`.
+    pub fn set_texture(&mut self, texture: Image) {
+        self.layer_mut(LayerId::MAIN).set_texture(texture)
+    }
+
+    pub fn set_cell_size(&mut self, cell_width: u32, cell_height: u32) {
+        self.layer_mut(LayerId::MAIN).set_cell_size(cell_width, cell_height)
+    }
+
+    /// The glyph styles of the main layer. See `Layer::glyph_styles`.
+    pub fn glyph_styles(&self) -> &GlyphStyles {
+        self.layer(LayerId::MAIN).glyph_styles()
+    }
+
+    /// Sets the style of a glyph of the main layer. See `Layer::set_glyph_style`.
+    pub fn set_glyph_style(&mut self, region: TextureRegion, style: GlyphStyle) {
+        self.layer_mut(LayerId::MAIN).set_glyph_style(region, style)
+    }
+
+    /// Adds a glyph bitmap to the main layer. See `Layer::add_glyph_image`.
+    pub fn add_glyph_image(&mut self, image: &Image) -> TextureRegion {
+        self.layer_mut(LayerId::MAIN).add_glyph_image(image)
+    }
+
+    /// Overwrites a glyph bitmap of the main layer. See `Layer::replace_glyph_image`.
+    pub fn replace_glyph_image(
+        &mut self,
+        region: TextureRegion,
+        image: &Image,
+    ) -> Result<(), ImageError> {
+        self.layer_mut(LayerId::MAIN).replace_glyph_image(region, image)
     }
 }
 
@@ -192,66 +597,13 @@ impl tcrab_console::Console for Console {
         C: tcrab_console::Canvas<G>,
     {
         let (width_cells, height_cells) = canvas.size();
-        let window_width = width_cells as u32 * self.cell_width;
-        let window_height = height_cells as u32 * self.cell_height;
-        self.windowed_context.window().set_inner_size(
-            glutin::dpi::LogicalSize::new(window_width as f64, window_height as f64));
-        self.windowed_context.window().show();
-        unsafe {
-            glcheck!(gl::ClearColor(1.0, 0.0, 1.0, 1.0));
-            glcheck!(gl::Clear(gl::COLOR_BUFFER_BIT));
-
-            glcheck!(gl::Viewport(0, 0, window_width as GLsizei, window_height as GLsizei));
-            glcheck!(gl::UseProgram(self.shader_program));
-            let projection_matrix = gfx::ortho_matrix(
-                0.0, window_width as f32,
-                0.0, window_height as f32,
-                -0.1, 1.0,
-            );
-            glcheck!(gl::UniformMatrix4fv(
-                self.projection_uniform_location,
-                1,
-                gl::FALSE,
-                projection_matrix.as_ptr(),
-            ));
-
-            glcheck!(gl::BindVertexArray(self.vertex_array_object));
-
-            let geom = gfx::gen_canvas_geometry(
-                canvas,
-                self.cell_width,
-                self.cell_height,
-                self.texture_width,
-                self.texture_height,
-                glyph_lib,
-            );
-            glcheck!(gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer_object));
-            glcheck!(gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (geom.vertices.len() * std::mem::size_of::<gfx::Vertex>()) as GLsizeiptr,
-                geom.vertices.as_ptr() as *const _,
-                gl::DYNAMIC_DRAW,
-            ));
-            glcheck!(gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.index_buffer_object));
-            glcheck!(gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (geom.indices.len() * std::mem::size_of::<gfx::Index>()) as GLsizeiptr,
-                geom.indices.as_ptr() as *const _,
-                gl::DYNAMIC_DRAW,
-            ));
-
-            glcheck!(gl::ActiveTexture(gl::TEXTURE0));
-            glcheck!(gl::Uniform1i(self.texture_uniform_location, 0));
-            glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.texture));
-
-            glcheck!(gl::DrawElements(
-                gl::TRIANGLES,
-                geom.indices.len() as GLsizei,
-                gfx::GL_INDEX_TYPE,
-                std::ptr::null(),
-            ));
-        }
-        self.windowed_context.swap_buffers().unwrap();
+        let (cell_width, cell_height) = self.layer(LayerId::MAIN).cell_size();
+        let mut frame = self.begin_frame(
+            width_cells as u32 * cell_width,
+            height_cells as u32 * cell_height,
+        );
+        frame.draw(LayerId::MAIN, canvas, glyph_lib, 0, 0);
+        frame.finish();
     }
 }
 
@@ -259,11 +611,16 @@ impl Drop for Console {
     fn drop(&mut self) {
         // We'll be nice and clean up our OpenGL stuff :)
         unsafe {
-            glcheck!(gl::DeleteTextures(1, &self.texture));
+            for layer in &self.layers {
+                glcheck!(gl::DeleteTextures(1, &layer.texture));
+            }
+            glcheck!(gl::DeleteTextures(1, &self.background_texture));
+            self.post.delete();
+            self.output.delete();
             glcheck!(gl::DeleteVertexArrays(1, &self.vertex_array_object));
             glcheck!(gl::DeleteBuffers(1, &self.vertex_buffer_object));
             glcheck!(gl::DeleteBuffers(1, &self.index_buffer_object));
-            glcheck!(gl::DeleteProgram(self.shader_program));
+            glcheck!(gl::DeleteProgram(self.cell_program.id));
         }
     }
 }
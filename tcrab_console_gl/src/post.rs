@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use gl::types::{GLint, GLsizei, GLuint};
+
+use crate::{gfx, ColorSpace, ShaderError};
+
+const VERTEX_SHADER_SRC: &[u8] = include_bytes!("../shaders/post_vertex.glsl");
+const CRT_SHADER_SRC: &str = include_str!("../shaders/post_crt.glsl");
+const SCANLINES_SHADER_SRC: &str = include_str!("../shaders/post_scanlines.glsl");
+const BLOOM_SHADER_SRC: &str = include_str!("../shaders/post_bloom.glsl");
+const COLOR_BLINDNESS_SHADER_SRC: &str = include_str!("../shaders/post_color_blindness.glsl");
+const COPY_SHADER_SRC: &str = include_str!("../shaders/post_copy.glsl");
+
+/// A full-screen pass run over each frame before it is shown. See `Console::set_post_effects`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostEffect {
+    /// Bends the frame like the curved glass of a CRT and darkens its corners. A `curvature`
+    /// around 0.1 and a `vignette` around 0.3 look like an old monitor.
+    Crt { curvature: f32, vignette: f32 },
+    /// Darkens lines of pixels by up to `intensity`, from 0 to 1. With a `lines_per_cell` of 0,
+    /// every other line of pixels is darkened.
+    Scanlines { intensity: f32, lines_per_cell: f32 },
+    /// Makes colors whose brightest channel is above `threshold` glow over `radius` pixels.
+    Bloom { threshold: f32, intensity: f32, radius: f32 },
+    /// Shows the frame the way players with a color vision deficiency see it. `severity` goes from
+    /// 0 to 1.
+    ColorBlindness { kind: ColorBlindness, severity: f32 },
+    /// A fragment shader in GLSL 3.30, which reads the frame so far and writes the processed
+    /// color. It can declare these inputs and uniforms:
+    ///
+    /// ```glsl
+    /// #version 330
+    ///
+    /// in vec2 v_TexCoord;          // From (0, 0) at the bottom left to (1, 1) at the top right.
+    /// out vec4 o_Color;
+    ///
+    /// uniform sampler2D u_Texture; // The frame, or the output of the previous effect.
+    /// uniform vec2 u_Resolution;   // The size of the frame in pixels.
+    /// uniform vec2 u_CellSize;     // The cell size of the main layer in pixels.
+    /// uniform float u_Time;        // Seconds since the console was created.
+    /// uniform vec4 u_Params;       // `params`.
+    /// ```
+    ///
+    /// In linear color space, the frame holds linear colors and the output is expected to be
+    /// linear too.
+    Custom { source: String, params: [f32; 4] },
+}
+
+impl PostEffect {
+    fn source(&self) -> &str {
+        match self {
+            PostEffect::Crt { .. } => CRT_SHADER_SRC,
+            PostEffect::Scanlines { .. } => SCANLINES_SHADER_SRC,
+            PostEffect::Bloom { .. } => BLOOM_SHADER_SRC,
+            PostEffect::ColorBlindness { .. } => COLOR_BLINDNESS_SHADER_SRC,
+            PostEffect::Custom { source, .. } => source,
+        }
+    }
+
+    /// The value of `u_Params`.
+    fn params(&self) -> [f32; 4] {
+        match *self {
+            PostEffect::Crt { curvature, vignette } => [curvature, vignette, 0.0, 0.0],
+            PostEffect::Scanlines { intensity, lines_per_cell } => {
+                [intensity, lines_per_cell, 0.0, 0.0]
+            }
+            PostEffect::Bloom { threshold, intensity, radius } => {
+                [threshold, intensity, radius, 0.0]
+            }
+            PostEffect::ColorBlindness { kind, severity } => {
+                [color_blindness_value(kind), severity, 0.0, 0.0]
+            }
+            PostEffect::Custom { params, .. } => params,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorBlindness {
+    /// No red cones.
+    Protanopia,
+    /// No green cones.
+    Deuteranopia,
+    /// No blue cones.
+    Tritanopia,
+    /// No color vision at all.
+    Achromatopsia,
+}
+
+/// The kind of color blindness in `u_Params`, matching the constants in
+/// `post_color_blindness.glsl`.
+fn color_blindness_value(kind: ColorBlindness) -> f32 {
+    match kind {
+        ColorBlindness::Protanopia => 0.0,
+        ColorBlindness::Deuteranopia => 1.0,
+        ColorBlindness::Tritanopia => 2.0,
+        ColorBlindness::Achromatopsia => 3.0,
+    }
+}
+
+struct Program {
+    id: GLuint,
+    texture_location: GLint,
+    resolution_location: GLint,
+    cell_size_location: GLint,
+    time_location: GLint,
+    params_location: GLint,
+}
+
+impl Program {
+    unsafe fn new(fragment_src: &str) -> Result<Program, ShaderError> {
+        let id = gfx::link_program(VERTEX_SHADER_SRC, fragment_src.as_bytes())?;
+        Ok(Program {
+            id,
+            texture_location: gfx::uniform_location(id, "u_Texture"),
+            resolution_location: gfx::uniform_location(id, "u_Resolution"),
+            cell_size_location: gfx::uniform_location(id, "u_CellSize"),
+            time_location: gfx::uniform_location(id, "u_Time"),
+            params_location: gfx::uniform_location(id, "u_Params"),
+        })
+    }
+}
+
+/// Runs the post-processing effects of a console.
+///
+/// While there are effects, frames are drawn into an offscreen texture instead of the output
+/// framebuffer. Each effect then draws a full-screen triangle sampling the output of the previous
+/// one, and the last effect draws into the output framebuffer. If the offscreen textures can't be
+/// drawn into, frames are drawn into the output framebuffer without effects.
+pub(crate) struct PostProcessor {
+    effects: Vec<PostEffect>,
+    /// The programs of the effects by fragment shader source, so setting the same effects again
+    /// doesn't recompile.
+    programs: HashMap<String, Program>,
+    /// Copies the output to the window.
+    copy_program: Program,
+    vertex_array_object: GLuint,
+    targets: [gfx::RenderTarget; 2],
+    /// The size of the frame being drawn into the first target, while effects run.
+    frame_size: Option<(u32, u32)>,
+    /// The framebuffer the frame being drawn ends up in.
+    output: GLuint,
+}
+
+impl PostProcessor {
+    pub unsafe fn new() -> Result<PostProcessor, ShaderError> {
+        let copy_program = Program::new(COPY_SHADER_SRC)?;
+        let mut vertex_array_object = 0;
+        glcheck!(gl::GenVertexArrays(1, &mut vertex_array_object));
+        Ok(PostProcessor {
+            effects: Vec::new(),
+            programs: HashMap::new(),
+            copy_program,
+            vertex_array_object,
+            targets: [gfx::RenderTarget::new(gl::LINEAR), gfx::RenderTarget::new(gl::LINEAR)],
+            frame_size: None,
+            output: 0,
+        })
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Compiles the shaders of `effects` that haven't been compiled yet. The current effects are
+    /// kept if one fails. Programs no longer used by the effects are deleted.
+    pub unsafe fn set_effects(&mut self, effects: Vec<PostEffect>) -> Result<(), ShaderError> {
+        let mut result = Ok(());
+        for effect in &effects {
+            if !self.programs.contains_key(effect.source()) {
+                match Program::new(effect.source()) {
+                    Ok(program) => {
+                        self.programs.insert(effect.source().to_owned(), program);
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+        }
+        if result.is_ok() {
+            self.effects = effects;
+        }
+        // Also drops the programs compiled for effects that failed.
+        let current = &self.effects;
+        self.programs.retain(|source, program| {
+            let used = current.iter().any(|effect| effect.source() == source);
+            if !used {
+                glcheck!(gl::DeleteProgram(program.id));
+            }
+            used
+        });
+        result
+    }
+
+    /// Binds the framebuffer a frame of `width` by `height` pixels is drawn into: an offscreen
+    /// one while there are effects, `output` otherwise.
+    pub unsafe fn begin(
+        &mut self,
+        width: u32,
+        height: u32,
+        color_space: ColorSpace,
+        output: GLuint,
+    ) {
+        self.frame_size = None;
+        self.output = output;
+        // The second target is bound first, so the first one ends up bound.
+        if !self.effects.is_empty()
+            && self.targets[1].bind(width, height, color_space)
+            && self.targets[0].bind(width, height, color_space)
+        {
+            self.frame_size = Some((width, height));
+        } else {
+            glcheck!(gl::BindFramebuffer(gl::FRAMEBUFFER, output));
+        }
+    }
+
+    /// Runs the effects over the frame drawn since `begin`, ending with the output framebuffer
+    /// bound. `time` is the value of `u_Time`.
+    pub unsafe fn finish(&self, cell_size: (u32, u32), time: f32) {
+        let (width, height) = match self.frame_size {
+            Some(size) => size,
+            None => return,
+        };
+
+        glcheck!(gl::Disable(gl::BLEND));
+        glcheck!(gl::BindVertexArray(self.vertex_array_object));
+        glcheck!(gl::ActiveTexture(gl::TEXTURE0));
+        // The frame is in the first texture, then effects alternate between the two.
+        let mut source = 0;
+        for (i, effect) in self.effects.iter().enumerate() {
+            let last = i + 1 == self.effects.len();
+            let target = if last { self.output } else { self.targets[1 - source].framebuffer };
+            glcheck!(gl::BindFramebuffer(gl::FRAMEBUFFER, target));
+            glcheck!(gl::BindTexture(gl::TEXTURE_2D, self.targets[source].texture));
+
+            let program = &self.programs[effect.source()];
+            let [a, b, c, d] = effect.params();
+            glcheck!(gl::UseProgram(program.id));
+            glcheck!(gl::Uniform1i(program.texture_location, 0));
+            glcheck!(gl::Uniform2f(program.resolution_location, width as f32, height as f32));
+            glcheck!(gl::Uniform2f(
+                program.cell_size_location,
+                cell_size.0 as f32,
+                cell_size.1 as f32,
+            ));
+            glcheck!(gl::Uniform1f(program.time_location, time));
+            glcheck!(gl::Uniform4f(program.params_location, a, b, c, d));
+            glcheck!(gl::DrawArrays(gl::TRIANGLES, 0, 3));
+            source = 1 - source;
+        }
+    }
+
+    /// Draws `texture` over the whole window, whose framebuffer is `width` by `height` pixels.
+    pub unsafe fn present(&self, texture: GLuint, width: u32, height: u32) {
+        glcheck!(gl::BindFramebuffer(gl::FRAMEBUFFER, 0));
+        glcheck!(gl::Viewport(0, 0, width as GLsizei, height as GLsizei));
+        glcheck!(gl::Disable(gl::BLEND));
+        glcheck!(gl::BindVertexArray(self.vertex_array_object));
+        glcheck!(gl::ActiveTexture(gl::TEXTURE0));
+        glcheck!(gl::BindTexture(gl::TEXTURE_2D, texture));
+        glcheck!(gl::UseProgram(self.copy_program.id));
+        glcheck!(gl::Uniform1i(self.copy_program.texture_location, 0));
+        glcheck!(gl::DrawArrays(gl::TRIANGLES, 0, 3));
+    }
+
+    pub unsafe fn delete(&self) {
+        for program in self.programs.values() {
+            glcheck!(gl::DeleteProgram(program.id));
+        }
+        glcheck!(gl::DeleteProgram(self.copy_program.id));
+        for target in &self.targets {
+            target.delete();
+        }
+        glcheck!(gl::DeleteVertexArrays(1, &self.vertex_array_object));
+    }
+}
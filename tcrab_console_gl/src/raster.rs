@@ -0,0 +1,355 @@
+use tcrab_console::canvas::{CustomGlyph, Glyph, GlyphLibrary};
+use tcrab_console::{Canvas, Color};
+
+use crate::{gfx, Background, ColorSpace, GlyphStyles, Image, RenderMode, TextureRegion};
+
+/// Draws `canvas` into a new, transparent image on the CPU, without needing a GPU or a window.
+///
+/// Follows the same rules as the OpenGL renderer: cell backgrounds are drawn first, then glyphs are
+/// blended over them using the texture's alpha and the foreground alpha, with nearest-neighbor
+/// sampling. Blending happens in `color_space`. Blinking cells are always shown.
+pub fn rasterize<G, C>(
+    canvas: &C,
+    glyph_lib: &GlyphLibrary<G, TextureRegion>,
+    glyph_styles: &GlyphStyles,
+    texture: &Image,
+    cell_width: u32,
+    cell_height: u32,
+    color_space: ColorSpace,
+) -> Image
+where
+    G: CustomGlyph,
+    C: Canvas<G>,
+{
+    let (width_cells, height_cells) = canvas.size();
+    let width = width_cells as u32 * cell_width;
+    let height = height_cells as u32 * cell_height;
+    let mut image = Image::blank(width, height);
+    rasterize_into(
+        &mut image,
+        canvas,
+        glyph_lib,
+        glyph_styles,
+        texture,
+        cell_width,
+        cell_height,
+        0,
+        0,
+        color_space,
+    );
+    image
+}
+
+/// Draws `canvas` over `target` with its top left corner at `(x, y)`. Parts outside of `target`
+/// are clipped.
+#[allow(clippy::too_many_arguments)]
+pub fn rasterize_into<G, C>(
+    target: &mut Image,
+    canvas: &C,
+    glyph_lib: &GlyphLibrary<G, TextureRegion>,
+    glyph_styles: &GlyphStyles,
+    texture: &Image,
+    cell_width: u32,
+    cell_height: u32,
+    x: i32,
+    y: i32,
+    color_space: ColorSpace,
+) where
+    G: CustomGlyph,
+    C: Canvas<G>,
+{
+    let (width_cells, height_cells) = canvas.size();
+    let cell_origin = |cell_x: usize, cell_y: usize| {
+        (x + (cell_x as u32 * cell_width) as i32, y + (cell_y as u32 * cell_height) as i32)
+    };
+
+    // All backgrounds go first, so glyphs covering several cells are drawn over the backgrounds
+    // of their neighbors.
+    for cell_y in 0..height_cells {
+        for cell_x in 0..width_cells {
+            let cell = canvas.get_cell(cell_x, cell_y);
+            let (left, top) = cell_origin(cell_x, cell_y);
+            let (_, background_color) = gfx::cell_colors(&cell);
+            let (color, alpha) = shader_color(background_color, color_space);
+            fill_rect(target, left, top, cell_width, cell_height, color, alpha, color_space);
+        }
+    }
+
+    for cell_y in 0..height_cells {
+        for cell_x in 0..width_cells {
+            let cell = canvas.get_cell(cell_x, cell_y);
+            if let Glyph::Continuation = cell.glyph {
+                continue;
+            }
+            let region = *glyph_lib.get_glyph_def(cell.glyph);
+            let style = glyph_styles.get(region);
+            let (left, top) = cell_origin(cell_x, cell_y);
+            let (left, top, width, height) = match style.placement {
+                Some(placement) => (
+                    left + placement.x,
+                    top + placement.y,
+                    placement.width,
+                    placement.height,
+                ),
+                None => (
+                    left,
+                    top,
+                    style.span.0 * cell_width,
+                    style.span.1 * cell_height,
+                ),
+            };
+            let (foreground_color, _) = gfx::cell_colors(&cell);
+            let (foreground, foreground_alpha) = shader_color(foreground_color, color_space);
+            for dst_y in top.max(0)..(top + height as i32).min(target.height as i32) {
+                // Sample at pixel centers, like the GPU.
+                let v = ((dst_y - top) as f32 + 0.5) / height as f32;
+                let src_y = region.y + (v * region.height as f32) as u32;
+                for dst_x in left.max(0)..(left + width as i32).min(target.width as i32) {
+                    let u = ((dst_x - left) as f32 + 0.5) / width as f32;
+                    let src_x = region.x + (u * region.width as f32) as u32;
+                    let (texel_color, texel_alpha) = texel(texture, src_x, src_y, color_space);
+                    let glyph_color = glyph_color(style.render_mode, foreground, texel_color);
+                    let alpha = texel_alpha * foreground_alpha;
+                    let (dst_x, dst_y) = (dst_x as u32, dst_y as u32);
+                    blend_pixel(target, dst_x, dst_y, glyph_color, alpha, color_space);
+                }
+            }
+        }
+    }
+
+    // Lines go over glyphs, including the continuation cells of a glyph so they are unbroken.
+    for cell_y in 0..height_cells {
+        for cell_x in 0..width_cells {
+            let cell = canvas.get_cell(cell_x, cell_y);
+            let (left, top) = cell_origin(cell_x, cell_y);
+            let (foreground_color, _) = gfx::cell_colors(&cell);
+            let (color, alpha) = shader_color(foreground_color, color_space);
+            for (line_y, line_height) in gfx::attribute_lines(cell.attributes, cell_height) {
+                let line_top = top + line_y as i32;
+                let (width, height) = (cell_width, line_height);
+                fill_rect(target, left, line_top, width, height, color, alpha, color_space);
+            }
+        }
+    }
+}
+
+/// The color of a glyph's pixel before blending, matching `fragment.glsl`.
+fn glyph_color(render_mode: RenderMode, foreground: [f32; 3], texel: [f32; 3]) -> [f32; 3] {
+    match render_mode {
+        RenderMode::AlphaMask => foreground,
+        RenderMode::FullColor => texel,
+        RenderMode::Tinted => [
+            texel[0] * foreground[0],
+            texel[1] * foreground[1],
+            texel[2] * foreground[2],
+        ],
+    }
+}
+
+/// A color as the shader sees it: linear in linear color space, and its alpha.
+fn shader_color(color: Color, color_space: ColorSpace) -> ([f32; 3], f32) {
+    let rgb = match color_space {
+        ColorSpace::Srgb => color.to_rgb_f32(),
+        ColorSpace::Linear => color.to_linear_rgb_f32(),
+    };
+    (rgb, color.a as f32 / 255.0)
+}
+
+/// Fills `target` with `clear_color` and draws `background` over it, like a frame of the renderer
+/// starts. Backgrounds are sampled at the nearest texel.
+pub(crate) fn clear(
+    target: &mut Image,
+    clear_color: Color,
+    background: Option<&Background>,
+    color_space: ColorSpace,
+) {
+    for pixel in target.data.chunks_mut(4) {
+        pixel.copy_from_slice(&[clear_color.r, clear_color.g, clear_color.b, clear_color.a]);
+    }
+    let background = match background {
+        Some(background) => background,
+        None => return,
+    };
+    let image = background.image();
+    if image.width == 0 || image.height == 0 {
+        return;
+    }
+    for y in 0..target.height {
+        for x in 0..target.width {
+            let (tex_x, tex_y) = match background {
+                Background::Stretched(_) => (
+                    (x as u64 * image.width as u64 / target.width as u64) as u32,
+                    (y as u64 * image.height as u64 / target.height as u64) as u32,
+                ),
+                Background::Tiled(_) => (x % image.width, y % image.height),
+            };
+            let (color, alpha) = texel(image, tex_x, tex_y, color_space);
+            blend_pixel(target, x, y, color, alpha, color_space);
+        }
+    }
+}
+
+/// Samples a texel like the shader does: linear in linear color space, where textures are sRGB.
+fn texel(image: &Image, x: u32, y: u32, color_space: ColorSpace) -> ([f32; 3], f32) {
+    let index = (y as usize * image.width as usize + x as usize) * 4;
+    let p = &image.data[index..index + 4];
+    shader_color(Color::from_rgba_u8([p[0], p[1], p[2], p[3]]), color_space)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fill_rect(
+    image: &mut Image,
+    left: i32,
+    top: i32,
+    width: u32,
+    height: u32,
+    color: [f32; 3],
+    alpha: f32,
+    color_space: ColorSpace,
+) {
+    for y in top.max(0)..(top + height as i32).min(image.height as i32) {
+        for x in left.max(0)..(left + width as i32).min(image.width as i32) {
+            blend_pixel(image, x as u32, y as u32, color, alpha, color_space);
+        }
+    }
+}
+
+/// Blends `color` over a pixel like the renderer's blend function: `GL_SRC_ALPHA,
+/// GL_ONE_MINUS_SRC_ALPHA` for colors and `GL_ONE, GL_ONE_MINUS_SRC_ALPHA` for alpha. In linear
+/// color space, the pixel is converted to linear and back, like an sRGB framebuffer does.
+fn blend_pixel(
+    image: &mut Image,
+    x: u32,
+    y: u32,
+    color: [f32; 3],
+    alpha: f32,
+    color_space: ColorSpace,
+) {
+    let index = (y as usize * image.width as usize + x as usize) * 4;
+    let dst = &mut image.data[index..index + 4];
+    let (dst_color, dst_alpha) =
+        shader_color(Color::from_rgba_u8([dst[0], dst[1], dst[2], dst[3]]), color_space);
+    let mut blended = [0.0; 3];
+    for channel in 0..3 {
+        blended[channel] = color[channel] * alpha + dst_color[channel] * (1.0 - alpha);
+    }
+    let blended = match color_space {
+        ColorSpace::Srgb => {
+            let channel = |c: f32| (c * 255.0).round().clamp(0.0, 255.0) as u8;
+            Color::from_rgba_u8([channel(blended[0]), channel(blended[1]), channel(blended[2]), 0])
+        }
+        ColorSpace::Linear => Color::from_linear_rgb_f32(blended),
+    };
+    dst[0] = blended.r;
+    dst[1] = blended.g;
+    dst[2] = blended.b;
+    dst[3] = ((alpha + dst_alpha * (1.0 - alpha)) * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use tcrab_console::canvas::{Cell, CellBuffer};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl tcrab_console::canvas::CustomGlyph for Tile {}
+
+    /// A texture with an opaque, a half covered and a transparent texel, one glyph each.
+    fn setup() -> (GlyphLibrary<Tile, TextureRegion>, Image) {
+        let texture = Image::new(3, 1, vec![
+            255, 255, 255, 255,
+            255, 255, 255, 128,
+            255, 255, 255, 0,
+        ]).unwrap();
+        let mut glyph_lib = GlyphLibrary::new();
+        for (x, c) in ['#', '+', ' '].iter().enumerate() {
+            glyph_lib.define_glyph(*c, TextureRegion { x: x as u32, y: 0, width: 1, height: 1 });
+        }
+        (glyph_lib, texture)
+    }
+
+    fn cell(c: char, foreground_color: Color, background_color: Color) -> Cell<Tile> {
+        Cell { glyph: c.into(), foreground_color, background_color, ..Cell::default() }
+    }
+
+    fn pixel(image: &Image, x: u32, y: u32) -> [u8; 4] {
+        let index = (y * image.width + x) as usize * 4;
+        [image.data[index], image.data[index + 1], image.data[index + 2], image.data[index + 3]]
+    }
+
+    #[test]
+    fn rasterize_draws_backgrounds_and_glyphs() {
+        let (glyph_lib, texture) = setup();
+        let mut canvas = CellBuffer::new(2, 1, Cell::default());
+        canvas.set_cell(0, 0, cell('#', Color::RED, Color::BLUE));
+        canvas.set_cell(1, 0, cell(' ', Color::RED, Color::GREEN));
+        for &color_space in &[ColorSpace::Srgb, ColorSpace::Linear] {
+            let styles = GlyphStyles::new();
+            let image = rasterize(&canvas, &glyph_lib, &styles, &texture, 2, 2, color_space);
+            assert_eq!((image.width, image.height), (4, 2));
+            assert_eq!(pixel(&image, 1, 1), [255, 0, 0, 255]);
+            assert_eq!(pixel(&image, 2, 0), [0, 255, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn rasterize_blends_in_the_color_space() {
+        let (glyph_lib, texture) = setup();
+        let canvas = CellBuffer::new(1, 1, cell('+', Color::WHITE, Color::BLACK));
+        let styles = GlyphStyles::new();
+        let srgb = rasterize(&canvas, &glyph_lib, &styles, &texture, 1, 1, ColorSpace::Srgb);
+        assert_eq!(pixel(&srgb, 0, 0), [128, 128, 128, 255]);
+        let linear = rasterize(&canvas, &glyph_lib, &styles, &texture, 1, 1, ColorSpace::Linear);
+        assert_eq!(pixel(&linear, 0, 0), [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn rasterize_into_offsets_and_clips() {
+        let (glyph_lib, texture) = setup();
+        let mut canvas = CellBuffer::new(2, 1, Cell::default());
+        canvas.set_cell(0, 0, cell('#', Color::RED, Color::BLUE));
+        canvas.set_cell(1, 0, cell('#', Color::GREEN, Color::BLUE));
+        for &color_space in &[ColorSpace::Srgb, ColorSpace::Linear] {
+            let mut target = Image::blank(2, 2);
+            let styles = GlyphStyles::new();
+            rasterize_into(
+                &mut target,
+                &canvas,
+                &glyph_lib,
+                &styles,
+                &texture,
+                1,
+                1,
+                -1,
+                1,
+                color_space,
+            );
+            assert_eq!(pixel(&target, 0, 1), [0, 255, 0, 255]);
+            for &(x, y) in &[(0, 0), (1, 0), (1, 1)] {
+                assert_eq!(pixel(&target, x, y), [0; 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn blend_pixel_in_srgb() {
+        let mut image = Image::new(1, 1, vec![0, 0, 0, 0]).unwrap();
+        blend_pixel(&mut image, 0, 0, [1.0; 3], 0.5, ColorSpace::Srgb);
+        assert_eq!(image.data, [128, 128, 128, 128]);
+        blend_pixel(&mut image, 0, 0, [0.0; 3], 0.5, ColorSpace::Srgb);
+        assert_eq!(image.data, [64, 64, 64, 192]);
+    }
+
+    #[test]
+    fn blend_pixel_in_linear() {
+        let mut image = Image::new(1, 1, vec![0, 0, 0, 0]).unwrap();
+        // Half of linear white is much brighter than half of sRGB white.
+        blend_pixel(&mut image, 0, 0, [1.0; 3], 0.5, ColorSpace::Linear);
+        assert_eq!(image.data, [188, 188, 188, 128]);
+        blend_pixel(&mut image, 0, 0, [1.0; 3], 1.0, ColorSpace::Linear);
+        assert_eq!(image.data, [255, 255, 255, 255]);
+    }
+}
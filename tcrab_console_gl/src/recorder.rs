@@ -0,0 +1,456 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use tcrab_console::canvas::{CustomGlyph, GlyphLibrary};
+use tcrab_console::{Canvas, ControlFlow, Event};
+
+use crate::{raster, ColorSpace, GlyphStyles, Image, SoftwareConsole, TextureRegion};
+
+/// How long the last frame of a recording is shown, since nothing follows it to end it.
+const LAST_FRAME_DURATION: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(std::io::Error),
+    #[cfg(feature = "gif")]
+    Gif(gif::EncodingError),
+    #[cfg(feature = "png")]
+    Png(png::EncodingError),
+    /// No frame was recorded, and animated images need at least one.
+    Empty,
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RecordError::Io(err) => write!(f, "{}", err),
+            #[cfg(feature = "gif")]
+            RecordError::Gif(err) => write!(f, "{}", err),
+            #[cfg(feature = "png")]
+            RecordError::Png(err) => write!(f, "{}", err),
+            RecordError::Empty => write!(f, "no frames were recorded"),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<std::io::Error> for RecordError {
+    fn from(err: std::io::Error) -> RecordError {
+        RecordError::Io(err)
+    }
+}
+
+#[cfg(feature = "gif")]
+impl From<gif::EncodingError> for RecordError {
+    fn from(err: gif::EncodingError) -> RecordError {
+        RecordError::Gif(err)
+    }
+}
+
+#[cfg(feature = "png")]
+impl From<png::EncodingError> for RecordError {
+    fn from(err: png::EncodingError) -> RecordError {
+        RecordError::Png(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordingFormat {
+    /// An animated GIF. Each frame is reduced to a 256 color palette.
+    #[cfg(feature = "gif")]
+    Gif,
+    /// An animated PNG. Lossless, but all frames are kept in memory until the recording is
+    /// finished.
+    #[cfg(feature = "png")]
+    Apng,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecorderOptions {
+    /// Merge identical consecutive frames into one longer frame. Turn-based games often present
+    /// the same frame many times.
+    pub skip_duplicate_frames: bool,
+    /// Drop frames recorded less than `1 / max_fps` seconds after the last kept frame. The last
+    /// dropped frame is still added when the recording is finished, so it ends on the final
+    /// state. Values that aren't positive, including NaN, are ignored.
+    pub max_fps: Option<f32>,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> RecorderOptions {
+        RecorderOptions {
+            skip_duplicate_frames: true,
+            max_fps: None,
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    #[cfg(feature = "gif")]
+    Gif {
+        writer: Option<W>,
+        encoder: Option<gif::Encoder<W>>,
+    },
+    #[cfg(feature = "png")]
+    Apng {
+        writer: W,
+        frames: Vec<(Image, Duration)>,
+    },
+}
+
+/// Encodes a sequence of frames into an animated image.
+///
+/// All frames are cropped or padded to the size of the first one.
+pub struct Recorder<W: Write> {
+    options: RecorderOptions,
+    /// The shortest time between kept frames, from `RecorderOptions::max_fps`.
+    min_frame_interval: Option<Duration>,
+    encoder: Encoder<W>,
+    size: Option<(u32, u32)>,
+    /// The last kept frame and its timestamp, encoded once the next frame gives its duration.
+    pending: Option<(Image, Duration)>,
+    /// The newest frame dropped because of `max_fps`, and its timestamp.
+    dropped: Option<(Image, Duration)>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W, format: RecordingFormat, options: RecorderOptions) -> Recorder<W> {
+        let encoder = match format {
+            #[cfg(feature = "gif")]
+            RecordingFormat::Gif => Encoder::Gif { writer: Some(writer), encoder: None },
+            #[cfg(feature = "png")]
+            RecordingFormat::Apng => Encoder::Apng { writer, frames: Vec::new() },
+        };
+        let min_frame_interval = options
+            .max_fps
+            .filter(|&max_fps| max_fps > 0.0)
+            .map(|max_fps| {
+                Duration::try_from_secs_f32(1.0 / max_fps).unwrap_or(Duration::MAX)
+            });
+        Recorder {
+            options,
+            min_frame_interval,
+            encoder,
+            size: None,
+            pending: None,
+            dropped: None,
+        }
+    }
+
+    /// Adds a frame shown from `timestamp`, measured from the start of the recording, until the
+    /// next frame. A timestamp before the previous one ends the previous frame immediately.
+    pub fn record_frame(&mut self, image: Image, timestamp: Duration) -> Result<(), RecordError> {
+        if let (Some(interval), Some((_, last))) = (self.min_frame_interval, &self.pending) {
+            if timestamp.saturating_sub(*last) < interval {
+                self.dropped = Some((image, timestamp));
+                return Ok(());
+            }
+        }
+        self.dropped = None;
+        self.add_frame(image, timestamp)
+    }
+
+    fn add_frame(&mut self, mut image: Image, timestamp: Duration) -> Result<(), RecordError> {
+        let (width, height) = *self.size.get_or_insert((image.width, image.height));
+        image.resize(width, height);
+        if let Some((pending_image, pending_timestamp)) = self.pending.take() {
+            if self.options.skip_duplicate_frames && pending_image == image {
+                self.pending = Some((pending_image, pending_timestamp));
+                return Ok(());
+            }
+            self.encode(pending_image, timestamp.saturating_sub(pending_timestamp))?;
+        }
+        self.pending = Some((image, timestamp));
+        Ok(())
+    }
+
+    /// Writes the remaining frames and the end of the file. Fails with `RecordError::Empty` if no
+    /// frame was recorded.
+    pub fn finish(mut self) -> Result<(), RecordError> {
+        if let Some((image, timestamp)) = self.dropped.take() {
+            self.add_frame(image, timestamp)?;
+        }
+        if let Some((image, _)) = self.pending.take() {
+            self.encode(image, LAST_FRAME_DURATION)?;
+        }
+        if self.size.is_none() {
+            return Err(RecordError::Empty);
+        }
+        match self.encoder {
+            #[cfg(feature = "gif")]
+            Encoder::Gif { encoder, .. } => {
+                // Dropping the encoder writes the trailer.
+                drop(encoder);
+            }
+            #[cfg(feature = "png")]
+            Encoder::Apng { writer, frames } => {
+                let (width, height) = self.size.unwrap(); // Checked above
+                let mut encoder = png::Encoder::new(writer, width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames.len() as u32, 0)?;
+                let mut writer = encoder.write_header()?;
+                for (image, duration) in &frames {
+                    let millis = duration.as_millis().min(u16::MAX as u128) as u16;
+                    writer.set_frame_delay(millis, 1000)?;
+                    writer.write_image_data(&image.data)?;
+                }
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, image: Image, duration: Duration) -> Result<(), RecordError> {
+        match &mut self.encoder {
+            #[cfg(feature = "gif")]
+            Encoder::Gif { writer, encoder } => {
+                if encoder.is_none() {
+                    // Only `None` once the encoder has been created, and it's never taken back.
+                    let writer = writer.take().unwrap();
+                    let mut new_encoder =
+                        gif::Encoder::new(writer, image.width as u16, image.height as u16, &[])?;
+                    new_encoder.set_repeat(gif::Repeat::Infinite)?;
+                    *encoder = Some(new_encoder);
+                }
+                let (width, height) = (image.width as u16, image.height as u16);
+                let mut data = image.data;
+                let mut frame = gif::Frame::from_rgba_speed(width, height, &mut data, 10);
+                // GIF delays are in hundredths of a second.
+                frame.delay = (duration.as_millis() / 10).min(u16::MAX as u128) as u16;
+                encoder.as_mut().unwrap().write_frame(&frame)?;
+            }
+            #[cfg(feature = "png")]
+            Encoder::Apng { frames, .. } => frames.push((image, duration)),
+        }
+        Ok(())
+    }
+}
+
+/// Where a `Recording` takes its frames from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameSource {
+    /// Read back each frame once it is drawn, after any post-processing effects.
+    Framebuffer,
+    /// Render the presented canvas on the CPU with `rasterize`, using the console's texture.
+    Software,
+}
+
+/// A console whose frames can be recorded.
+pub trait Capture: tcrab_console::Console<GlyphDef = TextureRegion> {
+    /// Returns the last presented frame.
+    fn capture_framebuffer(&self) -> Image;
+    fn glyph_texture(&self) -> &Image;
+    fn glyph_styles(&self) -> &GlyphStyles;
+    fn cell_size(&self) -> (u32, u32);
+    fn color_space(&self) -> ColorSpace;
+}
+
+impl Capture for crate::Console {
+    fn capture_framebuffer(&self) -> Image {
+        self.screenshot()
+    }
+
+    fn glyph_texture(&self) -> &Image {
+        self.texture()
+    }
+
+    fn glyph_styles(&self) -> &GlyphStyles {
+        crate::Console::glyph_styles(self)
+    }
+
+    fn cell_size(&self) -> (u32, u32) {
+        self.layer(crate::LayerId::MAIN).cell_size()
+    }
+
+    fn color_space(&self) -> ColorSpace {
+        crate::Console::color_space(self)
+    }
+}
+
+impl Capture for SoftwareConsole {
+    fn capture_framebuffer(&self) -> Image {
+        self.framebuffer().clone()
+    }
+
+    fn glyph_texture(&self) -> &Image {
+        self.texture()
+    }
+
+    fn glyph_styles(&self) -> &GlyphStyles {
+        SoftwareConsole::glyph_styles(self)
+    }
+
+    fn cell_size(&self) -> (u32, u32) {
+        SoftwareConsole::cell_size(self)
+    }
+
+    fn color_space(&self) -> ColorSpace {
+        SoftwareConsole::color_space(self)
+    }
+}
+
+/// Wraps a console to record every presented frame, timed by when it was presented.
+pub struct Recording<C: Capture, W: Write> {
+    console: C,
+    recorder: Recorder<W>,
+    source: FrameSource,
+    start: Instant,
+    error: Option<RecordError>,
+}
+
+impl<C: Capture, W: Write> Recording<C, W> {
+    pub fn new(console: C, recorder: Recorder<W>, source: FrameSource) -> Recording<C, W> {
+        Recording {
+            console,
+            recorder,
+            source,
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    pub fn console(&self) -> &C {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut C {
+        &mut self.console
+    }
+
+    /// Finishes the recording and gives back the console. Returns the first error that occurred
+    /// while recording, if any.
+    pub fn finish(self) -> Result<C, RecordError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.recorder.finish()?;
+        Ok(self.console)
+    }
+}
+
+impl<C: Capture, W: Write> tcrab_console::Console for Recording<C, W> {
+    type GlyphDef = TextureRegion;
+
+    fn wait_for_events_forever<F>(&mut self, event_handler: F)
+    where
+        F: FnMut(Event) -> ControlFlow,
+    {
+        self.console.wait_for_events_forever(event_handler);
+    }
+
+    fn present<G, V>(&mut self, canvas: &V, glyph_lib: &GlyphLibrary<G, TextureRegion>)
+    where
+        G: CustomGlyph,
+        V: Canvas<G>,
+    {
+        self.console.present(canvas, glyph_lib);
+        if self.error.is_some() {
+            return;
+        }
+        let image = match self.source {
+            FrameSource::Framebuffer => self.console.capture_framebuffer(),
+            FrameSource::Software => {
+                let (cell_width, cell_height) = self.console.cell_size();
+                raster::rasterize(
+                    canvas,
+                    glyph_lib,
+                    self.console.glyph_styles(),
+                    self.console.glyph_texture(),
+                    cell_width,
+                    cell_height,
+                    self.console.color_space(),
+                )
+            }
+        };
+        if let Err(err) = self.recorder.record_frame(image, self.start.elapsed()) {
+            self.error = Some(err);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "png"))]
+mod tests {
+    use super::*;
+
+    fn image(value: u8) -> Image {
+        Image::new(2, 1, vec![value; 8]).unwrap()
+    }
+
+    fn frame_count(data: &[u8]) -> u32 {
+        let reader = png::Decoder::new(data).read_info().unwrap();
+        reader.info().animation_control.unwrap().num_frames
+    }
+
+    #[test]
+    fn empty_recording() {
+        let recorder = Recorder::new(Vec::new(), RecordingFormat::Apng, Default::default());
+        assert!(matches!(recorder.finish(), Err(RecordError::Empty)));
+    }
+
+    #[test]
+    fn keeps_last_dropped_frame() {
+        let mut data = Vec::new();
+        let options = RecorderOptions { skip_duplicate_frames: true, max_fps: Some(10.0) };
+        let mut recorder = Recorder::new(&mut data, RecordingFormat::Apng, options);
+        recorder.record_frame(image(1), Duration::from_millis(0)).unwrap();
+        recorder.record_frame(image(2), Duration::from_millis(30)).unwrap();
+        recorder.record_frame(image(3), Duration::from_millis(60)).unwrap();
+        recorder.finish().unwrap();
+        assert_eq!(frame_count(&data), 2);
+    }
+
+    #[test]
+    fn merges_duplicate_frames() {
+        let mut data = Vec::new();
+        let mut recorder = Recorder::new(&mut data, RecordingFormat::Apng, Default::default());
+        for (i, &value) in [1, 1, 2, 2, 2, 1].iter().enumerate() {
+            recorder.record_frame(image(value), Duration::from_millis(i as u64 * 10)).unwrap();
+        }
+        recorder.finish().unwrap();
+        assert_eq!(frame_count(&data), 3);
+    }
+
+    #[test]
+    fn ignores_invalid_max_fps() {
+        for &max_fps in &[0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let mut data = Vec::new();
+            let options = RecorderOptions { skip_duplicate_frames: false, max_fps: Some(max_fps) };
+            let mut recorder = Recorder::new(&mut data, RecordingFormat::Apng, options);
+            for i in 0..3 {
+                recorder.record_frame(image(i), Duration::from_millis(i as u64)).unwrap();
+            }
+            recorder.finish().unwrap();
+            assert_eq!(frame_count(&data), 3);
+        }
+    }
+
+    #[test]
+    fn tiny_max_fps_keeps_first_and_last_frame() {
+        let mut data = Vec::new();
+        let options = RecorderOptions { skip_duplicate_frames: false, max_fps: Some(1e-40) };
+        let mut recorder = Recorder::new(&mut data, RecordingFormat::Apng, options);
+        for i in 0..3 {
+            recorder.record_frame(image(i), Duration::from_secs(i as u64 * 1000)).unwrap();
+        }
+        recorder.finish().unwrap();
+        assert_eq!(frame_count(&data), 2);
+    }
+
+    #[test]
+    fn accepts_timestamps_going_backwards() {
+        let mut data = Vec::new();
+        let mut recorder = Recorder::new(&mut data, RecordingFormat::Apng, Default::default());
+        recorder.record_frame(image(1), Duration::from_millis(50)).unwrap();
+        recorder.record_frame(image(2), Duration::from_millis(10)).unwrap();
+        recorder.finish().unwrap();
+        assert_eq!(frame_count(&data), 2);
+
+        let options = RecorderOptions { skip_duplicate_frames: false, max_fps: Some(10.0) };
+        let mut recorder = Recorder::new(Vec::new(), RecordingFormat::Apng, options);
+        recorder.record_frame(image(1), Duration::from_millis(50)).unwrap();
+        recorder.record_frame(image(2), Duration::from_millis(10)).unwrap();
+        recorder.finish().unwrap();
+    }
+}
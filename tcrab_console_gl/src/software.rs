@@ -0,0 +1,300 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use tcrab_console::canvas::{CustomGlyph, GlyphLibrary};
+use tcrab_console::{Canvas, Color, ControlFlow, Event};
+
+use crate::{raster, Background, ColorSpace, GlyphStyle, GlyphStyles, Image, ImageError};
+use crate::{Settings, TextureRegion};
+
+/// A console that renders into an in-memory framebuffer instead of a window.
+///
+/// It needs neither a GPU nor a display, so it can run on build servers. Frames are drawn with
+/// `rasterize`, which follows the same rules as the OpenGL renderer, so its output can serve as
+/// the reference image for tests of `Console`.
+///
+/// There is no input device: events have to be queued with `push_event`, or sent from another
+/// thread through `event_sender`.
+pub struct SoftwareConsole {
+    texture: Image,
+    glyph_styles: GlyphStyles,
+    cell_width: u32,
+    cell_height: u32,
+    color_space: ColorSpace,
+    clear_color: Color,
+    background: Option<Background>,
+    framebuffer: Image,
+    event_sender: Sender<Event>,
+    events: Receiver<Event>,
+}
+
+impl SoftwareConsole {
+    /// Creates a console from the same settings as `Console`. The title is ignored.
+    pub fn new(settings: Settings) -> Result<SoftwareConsole, ImageError> {
+        let (event_sender, events) = mpsc::channel();
+        Ok(SoftwareConsole {
+            texture: Image::new(
+                settings.texture_width,
+                settings.texture_height,
+                settings.texture_data,
+            )?,
+            glyph_styles: GlyphStyles::new(),
+            cell_width: settings.cell_width,
+            cell_height: settings.cell_height,
+            color_space: settings.color_space,
+            clear_color: settings.clear_color,
+            background: settings.background,
+            framebuffer: Image::blank(0, 0),
+            event_sender,
+            events,
+        })
+    }
+
+    /// The last presented frame.
+    pub fn framebuffer(&self) -> &Image {
+        &self.framebuffer
+    }
+
+    pub fn into_framebuffer(self) -> Image {
+        self.framebuffer
+    }
+
+    pub fn texture(&self) -> &Image {
+        &self.texture
+    }
+
+    pub fn set_texture(&mut self, texture: Image) {
+        self.texture = texture;
+    }
+
+    pub fn glyph_styles(&self) -> &GlyphStyles {
+        &self.glyph_styles
+    }
+
+    pub fn set_glyph_style(&mut self, region: TextureRegion, style: GlyphStyle) {
+        self.glyph_styles.set(region, style);
+    }
+
+    pub fn cell_size(&self) -> (u32, u32) {
+        (self.cell_width, self.cell_height)
+    }
+
+    pub fn set_cell_size(&mut self, cell_width: u32, cell_height: u32) {
+        self.cell_width = cell_width;
+        self.cell_height = cell_height;
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    pub fn set_clear_color(&mut self, clear_color: Color) {
+        self.clear_color = clear_color;
+    }
+
+    pub fn background(&self) -> Option<&Background> {
+        self.background.as_ref()
+    }
+
+    pub fn set_background(&mut self, background: Option<Background>) {
+        self.background = background;
+    }
+
+    /// Queues an event for the next call to `wait_for_events_forever` or `poll_events`.
+    pub fn push_event(&mut self, event: Event) {
+        self.event_sender.send(event).unwrap(); // The receiver lives as long as `self`.
+    }
+
+    /// Returns a sender queueing events from other threads, e.g. a test driver.
+    pub fn event_sender(&self) -> Sender<Event> {
+        self.event_sender.clone()
+    }
+
+    /// Passes queued events to `event_handler` until it breaks, without waiting for more.
+    pub fn poll_events<F>(&mut self, mut event_handler: F)
+    where
+        F: FnMut(Event) -> ControlFlow,
+    {
+        while let Ok(event) = self.events.try_recv() {
+            if event_handler(event) == ControlFlow::Break {
+                break;
+            }
+        }
+    }
+}
+
+impl tcrab_console::Console for SoftwareConsole {
+    type GlyphDef = TextureRegion;
+
+    /// Passes events to `event_handler` until it breaks, waiting for events sent through
+    /// `event_sender` once the queue is empty. Use `poll_events` to only handle queued events.
+    fn wait_for_events_forever<F>(&mut self, mut event_handler: F)
+    where
+        F: FnMut(Event) -> ControlFlow,
+    {
+        // `self` keeps a sender, so this only returns when the handler breaks.
+        while let Ok(event) = self.events.recv() {
+            if event_handler(event) == ControlFlow::Break {
+                break;
+            }
+        }
+    }
+
+    fn present<G, C>(&mut self, canvas: &C, glyph_lib: &GlyphLibrary<G, TextureRegion>)
+    where
+        G: CustomGlyph,
+        C: Canvas<G>,
+    {
+        let (width_cells, height_cells) = canvas.size();
+        let width = width_cells as u32 * self.cell_width;
+        let height = height_cells as u32 * self.cell_height;
+        let mut framebuffer = Image::blank(width, height);
+        let background = self.background.as_ref();
+        raster::clear(&mut framebuffer, self.clear_color, background, self.color_space);
+        raster::rasterize_into(
+            &mut framebuffer,
+            canvas,
+            glyph_lib,
+            &self.glyph_styles,
+            &self.texture,
+            self.cell_width,
+            self.cell_height,
+            0,
+            0,
+            self.color_space,
+        );
+        self.framebuffer = framebuffer;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tcrab_console::canvas::{Attributes, Cell, CellBuffer};
+    use tcrab_console::Console as _;
+
+    use super::*;
+    use crate::{Placement, RenderMode};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Tile;
+
+    impl CustomGlyph for Tile {}
+
+    const OPAQUE: TextureRegion = TextureRegion { x: 0, y: 0, width: 1, height: 1 };
+    const HALF: TextureRegion = TextureRegion { x: 1, y: 0, width: 1, height: 1 };
+    const TILE: TextureRegion = TextureRegion { x: 2, y: 0, width: 1, height: 1 };
+    const EMPTY: TextureRegion = TextureRegion { x: 3, y: 0, width: 1, height: 1 };
+
+    fn console(cell_size: u32) -> SoftwareConsole {
+        let texture = Image::new(4, 1, vec![
+            255, 255, 255, 255,
+            255, 255, 255, 128,
+            10, 20, 30, 255,
+            0, 0, 0, 0,
+        ]).unwrap();
+        SoftwareConsole::new(Settings::new("test", texture, cell_size, cell_size)).unwrap()
+    }
+
+    fn glyph_lib() -> GlyphLibrary<Tile, TextureRegion> {
+        let mut glyph_lib = GlyphLibrary::new();
+        glyph_lib.define_glyph('#', OPAQUE);
+        glyph_lib.define_glyph('+', HALF);
+        glyph_lib.define_glyph('T', TILE);
+        glyph_lib.define_glyph(' ', EMPTY);
+        glyph_lib
+    }
+
+    fn cell(c: char, foreground_color: Color, background_color: Color) -> Cell<Tile> {
+        Cell { glyph: c.into(), foreground_color, background_color, ..Cell::default() }
+    }
+
+    fn present(console: &mut SoftwareConsole, cell: Cell<Tile>) -> Vec<[u8; 4]> {
+        console.present(&CellBuffer::new(1, 1, cell), &glyph_lib());
+        let data = &console.framebuffer().data;
+        data.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()
+    }
+
+    #[test]
+    fn alpha_mask_mixes_foreground_over_background() {
+        let mut console = console(1);
+        assert_eq!(present(&mut console, cell('#', Color::RED, Color::BLUE)), [[255, 0, 0, 255]]);
+        let half = present(&mut console, cell('+', Color::WHITE, Color::BLACK));
+        assert_eq!(half, [[128, 128, 128, 255]]);
+        assert_eq!(present(&mut console, cell(' ', Color::RED, Color::BLUE)), [[0, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn full_color_and_tinted_glyphs() {
+        let mut console = console(1);
+        let magenta = Color::from_rgba_u8([255, 0, 255, 255]);
+        let style = GlyphStyle::default();
+        console.set_glyph_style(TILE, style.with_render_mode(RenderMode::FullColor));
+        assert_eq!(present(&mut console, cell('T', magenta, Color::BLACK)), [[10, 20, 30, 255]]);
+        console.set_glyph_style(TILE, style.with_render_mode(RenderMode::Tinted));
+        assert_eq!(present(&mut console, cell('T', magenta, Color::BLACK)), [[10, 0, 30, 255]]);
+    }
+
+    #[test]
+    fn reverse_swaps_colors() {
+        let mut console = console(1);
+        let cell = cell('#', Color::RED, Color::BLUE);
+        let reversed = Cell { attributes: Attributes::REVERSE, ..cell };
+        assert_eq!(present(&mut console, reversed), [[0, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn clear_color_shows_through_transparent_backgrounds() {
+        let mut console = console(1);
+        console.set_clear_color(Color::GREEN);
+        let transparent = Color::from_rgba_u8([0, 0, 0, 0]);
+        assert_eq!(present(&mut console, cell(' ', Color::RED, transparent)), [[0, 255, 0, 255]]);
+    }
+
+    #[test]
+    fn spanning_and_placed_glyphs() {
+        let mut console = console(2);
+        let tile = GlyphStyle::default().with_render_mode(RenderMode::FullColor);
+        console.set_glyph_style(TILE, tile.with_span(2, 1));
+        let mut canvas = CellBuffer::new(2, 1, cell(' ', Color::WHITE, Color::BLACK));
+        canvas.set_spanning_cell(0, 0, cell('T', Color::WHITE, Color::BLACK), 2, 1);
+        console.present(&canvas, &glyph_lib());
+        let framebuffer = console.framebuffer();
+        assert_eq!((framebuffer.width, framebuffer.height), (4, 2));
+        assert!(framebuffer.data.chunks_exact(4).all(|p| p == [10, 20, 30, 255]));
+
+        let placement = Placement { x: 1, y: 1, width: 1, height: 1 };
+        console.set_glyph_style(TILE, tile.with_placement(placement));
+        let pixels = present(&mut console, cell('T', Color::WHITE, Color::BLACK));
+        assert_eq!(pixels, [[0, 0, 0, 255], [0, 0, 0, 255], [0, 0, 0, 255], [10, 20, 30, 255]]);
+    }
+
+    #[test]
+    fn events_are_handled_until_the_handler_breaks() {
+        let mut console = console(1);
+        console.push_event(Event::Quit);
+        console.push_event(Event::Quit);
+        let mut handled = 0;
+        console.poll_events(|_| {
+            handled += 1;
+            ControlFlow::Break
+        });
+        assert_eq!(handled, 1);
+
+        let sender = console.event_sender();
+        let thread = std::thread::spawn(move || {
+            for _ in 0..2 {
+                sender.send(Event::Quit).unwrap();
+            }
+        });
+        // Waits for the events sent by the thread after the queued one.
+        console.wait_for_events_forever(|_| {
+            handled += 1;
+            if handled == 4 { ControlFlow::Break } else { ControlFlow::Continue }
+        });
+        thread.join().unwrap();
+        assert_eq!(handled, 4);
+    }
+}
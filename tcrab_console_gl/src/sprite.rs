@@ -0,0 +1,26 @@
+use tcrab_console::canvas::{CustomGlyph, Glyph, UserData};
+use tcrab_console::Color;
+
+/// A glyph drawn anywhere over a canvas rather than in one of its cells, e.g. a creature sliding
+/// between cells. See `Frame::draw_sprites` and `tcrab_console::tween::Tweener`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite<G: CustomGlyph> {
+    pub glyph: Glyph<G>,
+    /// The position of the glyph's top left cell, in cells. Fractions place it between cells.
+    pub x: f32,
+    pub y: f32,
+    pub color: Color,
+    pub user_data: UserData,
+}
+
+impl<G: CustomGlyph> Sprite<G> {
+    pub fn new<T: Into<Glyph<G>>>(glyph: T, x: f32, y: f32, color: Color) -> Sprite<G> {
+        Sprite {
+            glyph: glyph.into(),
+            x,
+            y,
+            color,
+            user_data: UserData::default(),
+        }
+    }
+}
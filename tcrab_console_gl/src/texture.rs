@@ -0,0 +1,309 @@
+#[cfg(feature = "image")]
+use tcrab_console::Color;
+
+use crate::TextureRegion;
+
+/// Describes where a tileset image keeps the coverage of its glyphs.
+///
+/// The renderer only looks at the alpha channel of the texture to decide how much of the
+/// foreground color to mix over the background, so every tileset has to be converted into that
+/// format before it is uploaded.
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlphaSource {
+    /// The image already has a meaningful alpha channel and is used as-is.
+    Alpha,
+    /// The brightness of each pixel is its coverage, e.g. white glyphs on a black background.
+    Greyscale,
+    /// Pixels matching this color (ignoring alpha) are transparent, everything else is opaque.
+    KeyColor(Color),
+}
+
+/// Pixel data doesn't match the size of the image or region it is meant for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// The data doesn't hold exactly `width * height` RGBA pixels.
+    DataLength { expected: usize, actual: usize },
+    /// A glyph bitmap isn't the same size as the region it replaces.
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    /// An image doesn't fit inside the destination at the given position.
+    OutOfBounds,
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImageError::DataLength { expected, actual } => {
+                write!(f, "expected {} bytes of RGBA data, got {}", expected, actual)
+            }
+            ImageError::SizeMismatch { expected, actual } => write!(
+                f,
+                "expected a {}x{} image, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            ImageError::OutOfBounds => write!(f, "image doesn't fit inside the destination"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// An RGBA image with 8 bits per channel, stored row by row from the top left.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<Image, ImageError> {
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4));
+        if expected != Some(data.len()) {
+            return Err(ImageError::DataLength {
+                expected: expected.unwrap_or(usize::MAX),
+                actual: data.len(),
+            });
+        }
+        Ok(Image { width, height, data })
+    }
+
+    /// Creates a fully transparent image.
+    pub(crate) fn blank(width: u32, height: u32) -> Image {
+        Image { width, height, data: vec![0; width as usize * height as usize * 4] }
+    }
+
+    /// Converts raw RGBA pixels into the alpha-mask format the renderer expects.
+    #[cfg(feature = "image")]
+    pub fn from_rgba_with_alpha_source(
+        width: u32,
+        height: u32,
+        mut data: Vec<u8>,
+        alpha_source: AlphaSource,
+    ) -> Image {
+        match alpha_source {
+            AlphaSource::Alpha => {}
+            AlphaSource::Greyscale => {
+                for pixel in data.chunks_exact_mut(4) {
+                    let luma = (pixel[0] as u32 * 299
+                        + pixel[1] as u32 * 587
+                        + pixel[2] as u32 * 114) / 1000;
+                    let alpha = luma * pixel[3] as u32 / 255;
+                    pixel.copy_from_slice(&[255, 255, 255, alpha as u8]);
+                }
+            }
+            AlphaSource::KeyColor(key) => {
+                for pixel in data.chunks_exact_mut(4) {
+                    if pixel[..3] == [key.r, key.g, key.b] {
+                        pixel.copy_from_slice(&[0, 0, 0, 0]);
+                    } else {
+                        pixel[3] = 255;
+                    }
+                }
+            }
+        }
+        Image { width, height, data }
+    }
+
+    /// Changes the size of the image, keeping existing pixels at the same position. New pixels are
+    /// transparent.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        let mut resized = Image::blank(width, height);
+        let copy_width = self.width.min(width) as usize * 4;
+        for y in 0..self.height.min(height) as usize {
+            let src = y * self.width as usize * 4;
+            let dst = y * width as usize * 4;
+            resized.data[dst..dst + copy_width].copy_from_slice(&self.data[src..src + copy_width]);
+        }
+        *self = resized;
+    }
+
+    /// Copies all of `src` into this image with its top left corner at `(x, y)`.
+    pub fn blit(&mut self, src: &Image, x: u32, y: u32) -> Result<(), ImageError> {
+        let fits_x = matches!(x.checked_add(src.width), Some(right) if right <= self.width);
+        let fits_y = matches!(y.checked_add(src.height), Some(bottom) if bottom <= self.height);
+        if !fits_x || !fits_y {
+            return Err(ImageError::OutOfBounds);
+        }
+        let row_len = src.width as usize * 4;
+        for row in 0..src.height as usize {
+            let src_start = row * row_len;
+            let dst_start = ((y as usize + row) * self.width as usize + x as usize) * 4;
+            self.data[dst_start..dst_start + row_len]
+                .copy_from_slice(&src.data[src_start..src_start + row_len]);
+        }
+        Ok(())
+    }
+
+    /// Decodes a PNG or BMP tileset from a file.
+    #[cfg(feature = "image")]
+    pub fn load<P: AsRef<std::path::Path>>(
+        path: P,
+        alpha_source: AlphaSource,
+    ) -> image::ImageResult<Image> {
+        Ok(Image::from_dynamic_image(image::open(path)?, alpha_source))
+    }
+
+    /// Decodes a PNG or BMP tileset from memory, e.g. from `include_bytes!`.
+    #[cfg(feature = "image")]
+    pub fn load_from_memory(bytes: &[u8], alpha_source: AlphaSource) -> image::ImageResult<Image> {
+        Ok(Image::from_dynamic_image(image::load_from_memory(bytes)?, alpha_source))
+    }
+
+    /// Encodes the image as a PNG file.
+    #[cfg(feature = "image")]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        image::png::PNGEncoder::new(std::io::BufWriter::new(file)).encode(
+            &self.data,
+            self.width,
+            self.height,
+            image::ColorType::RGBA(8),
+        )
+    }
+
+    #[cfg(feature = "image")]
+    fn from_dynamic_image(dynamic_image: image::DynamicImage, alpha_source: AlphaSource) -> Image {
+        let rgba = dynamic_image.to_rgba();
+        let (width, height) = rgba.dimensions();
+        Image::from_rgba_with_alpha_source(width, height, rgba.into_raw(), alpha_source)
+    }
+}
+
+/// A texture atlas made of equally sized slots, laid out in a fixed number of columns.
+///
+/// The atlas grows downwards by doubling its row count whenever it runs out of slots, so regions
+/// handed out earlier stay valid.
+#[derive(Debug, Clone)]
+pub(crate) struct GridAtlas {
+    image: Image,
+    slot_width: u32,
+    slot_height: u32,
+    columns: u32,
+    next_slot: u32,
+}
+
+impl GridAtlas {
+    pub fn new(slot_width: u32, slot_height: u32, columns: u32, rows: u32) -> GridAtlas {
+        let width = slot_width * columns;
+        let height = slot_height * rows.max(1);
+        GridAtlas {
+            image: Image::blank(width, height),
+            slot_width,
+            slot_height,
+            columns,
+            next_slot: 0,
+        }
+    }
+
+    #[cfg(feature = "rusttype")]
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+
+    /// Reserves the next free slot, growing the atlas if needed.
+    pub fn allocate(&mut self) -> TextureRegion {
+        let column = self.next_slot % self.columns;
+        let row = self.next_slot / self.columns;
+        self.next_slot += 1;
+        while (row + 1) * self.slot_height > self.image.height {
+            let new_height = self.image.height * 2;
+            self.image.data.resize(self.image.width as usize * new_height as usize * 4, 0);
+            self.image.height = new_height;
+        }
+        TextureRegion {
+            x: column * self.slot_width,
+            y: row * self.slot_height,
+            width: self.slot_width,
+            height: self.slot_height,
+        }
+    }
+
+    /// Writes a pixel relative to the top left of `region`. Pixels outside the region are
+    /// discarded.
+    pub fn put_pixel(&mut self, region: TextureRegion, x: u32, y: u32, rgba: [u8; 4]) {
+        if x >= region.width || y >= region.height {
+            return;
+        }
+        let index = ((region.y + y) as usize * self.image.width as usize
+            + (region.x + x) as usize) * 4;
+        self.image.data[index..index + 4].copy_from_slice(&rgba);
+    }
+}
+
+/// Packs arbitrarily sized images into rows ("shelves") below the existing contents of an image.
+#[derive(Debug, Clone)]
+pub(crate) struct ShelfPacker {
+    x: u32,
+    y: u32,
+    shelf_height: u32,
+}
+
+impl ShelfPacker {
+    /// Starts packing below the first `start_y` rows of the image.
+    pub fn new(start_y: u32) -> ShelfPacker {
+        ShelfPacker { x: 0, y: start_y, shelf_height: 0 }
+    }
+
+    /// Reserves a `width` by `height` region, growing `image` if it doesn't fit. Existing
+    /// contents keep their position, so previously packed regions stay valid.
+    pub fn pack(&mut self, image: &mut Image, width: u32, height: u32) -> TextureRegion {
+        if self.x + width > image.width.max(width) {
+            self.y += self.shelf_height;
+            self.x = 0;
+            self.shelf_height = 0;
+        }
+        let region = TextureRegion { x: self.x, y: self.y, width, height };
+        self.x += width;
+        self.shelf_height = self.shelf_height.max(height);
+
+        let mut new_width = image.width.max(1);
+        while new_width < region.x + region.width {
+            new_width *= 2;
+        }
+        let mut new_height = image.height.max(1);
+        while new_height < region.y + region.height {
+            new_height *= 2;
+        }
+        image.resize(new_width, new_height);
+        region
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_checks_data_length() {
+        assert!(Image::new(2, 2, vec![0; 16]).is_ok());
+        assert_eq!(
+            Image::new(2, 2, vec![0; 12]),
+            Err(ImageError::DataLength { expected: 16, actual: 12 })
+        );
+        assert_eq!(
+            Image::new(u32::MAX, u32::MAX, Vec::new()),
+            Err(ImageError::DataLength { expected: usize::MAX, actual: 0 })
+        );
+    }
+
+    #[test]
+    fn blit_rejects_images_outside_the_destination() {
+        let mut dst = Image::blank(4, 4);
+        let src = Image::new(2, 1, vec![255; 8]).unwrap();
+        dst.blit(&src, 2, 3).unwrap();
+        assert_eq!(&dst.data[(3 * 4 + 2) * 4..], &[255; 8]);
+        assert_eq!(dst.blit(&src, 3, 0), Err(ImageError::OutOfBounds));
+        assert_eq!(dst.blit(&src, u32::MAX, 0), Err(ImageError::OutOfBounds));
+        assert_eq!(dst.blit(&src, 0, u32::MAX), Err(ImageError::OutOfBounds));
+    }
+}